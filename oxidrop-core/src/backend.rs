@@ -0,0 +1,104 @@
+//! The protocol implementation an [`Oxidrop`](crate::Oxidrop) runs on.
+//!
+//! Everything `Oxidrop` asks of the protocol goes through [`ShareBackend`], so
+//! another protocol can be added by implementing it. Backends still speak in
+//! `rqs_lib`'s message types, which the rest of the crate is built around.
+
+use std::{future::Future, path::PathBuf, pin::Pin, sync::Arc};
+
+use rqs_lib::{
+    EndpointInfo, RQS, SendInfo, Visibility,
+    channel::{ChannelAction, ChannelDirection, ChannelMessage},
+};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{Config, Error, Result};
+
+pub(crate) type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+pub(crate) trait ShareBackend: Send {
+    /// Opens the listening socket and starts serving transfers. Returns where
+    /// to offer outbound transfers; the backend stops once that closes.
+    fn start(&mut self) -> BoxFuture<'_, Result<mpsc::Sender<SendInfo>>>;
+
+    /// Stops serving, ending every transfer.
+    fn stop(&mut self) -> BoxFuture<'_, ()>;
+
+    /// Where the state of every transfer is reported.
+    fn messages(&self) -> broadcast::Receiver<ChannelMessage>;
+
+    /// Answers or cancels transfer `id`.
+    fn act(&self, id: &str, action: ChannelAction) -> Result<()>;
+
+    /// Shows or hides this device to nearby ones.
+    fn advertise(&mut self, visibility: Visibility);
+
+    /// Starts sending nearby devices to `sender` as they're found.
+    fn discover(&mut self, sender: broadcast::Sender<EndpointInfo>) -> Result<()>;
+
+    fn stop_discovery(&mut self);
+
+    fn set_download_dir(&mut self, dir: Option<PathBuf>);
+}
+
+/// Makes the backends an instance runs on, one each time the service starts.
+pub(crate) type Factory = Arc<dyn Fn(&Config, Visibility) -> Box<dyn ShareBackend> + Send + Sync>;
+
+/// A backend for `config` that hasn't been started yet.
+pub(crate) fn new(config: &Config, visibility: Visibility) -> Box<dyn ShareBackend> {
+    Box::new(RQS::new(
+        visibility,
+        config.port.map(u32::from),
+        config.download_dir.clone(),
+    ))
+}
+
+/// Quick Share, as implemented by `rqs_lib`.
+impl ShareBackend for RQS {
+    fn start(&mut self) -> BoxFuture<'_, Result<mpsc::Sender<SendInfo>>> {
+        Box::pin(async move {
+            let (sendinfo_send, _) = self
+                .run()
+                .await
+                .map_err(|e| Error::Other(e.into_boxed_dyn_error()))?;
+            Ok(sendinfo_send)
+        })
+    }
+
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(RQS::stop(self))
+    }
+
+    fn messages(&self) -> broadcast::Receiver<ChannelMessage> {
+        self.message_sender.subscribe()
+    }
+
+    fn act(&self, id: &str, action: ChannelAction) -> Result<()> {
+        self.message_sender
+            .send(ChannelMessage {
+                id: id.to_string(),
+                direction: ChannelDirection::FrontToLib,
+                action: Some(action),
+                ..Default::default()
+            })
+            .map_err(|e| Error::Other(Box::new(e)))?;
+        Ok(())
+    }
+
+    fn advertise(&mut self, visibility: Visibility) {
+        self.change_visibility(visibility);
+    }
+
+    fn discover(&mut self, sender: broadcast::Sender<EndpointInfo>) -> Result<()> {
+        self.discovery(sender)
+            .map_err(|e| Error::Other(e.into_boxed_dyn_error()))
+    }
+
+    fn stop_discovery(&mut self) {
+        RQS::stop_discovery(self);
+    }
+
+    fn set_download_dir(&mut self, dir: Option<PathBuf>) {
+        self.set_download_path(dir);
+    }
+}
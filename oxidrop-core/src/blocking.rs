@@ -0,0 +1,124 @@
+//! A synchronous interface to [`Oxidrop`] for applications that don't use
+//! async Rust. It runs the service on a runtime of its own.
+
+use std::pin::Pin;
+
+use tokio::runtime::Runtime;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    Config, Endpoint, Error, File, Oxidrop, OxidropEvent, RejectReason, Result, TransferHandle,
+    TransferOutcome, TransferRequest, TransferState,
+};
+
+pub struct BlockingOxidrop {
+    runtime: Runtime,
+    oxidrop: Oxidrop,
+}
+
+impl BlockingOxidrop {
+    pub fn new(config: Config) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| Error::Other(Box::new(e)))?;
+        let oxidrop = runtime.block_on(Oxidrop::new(config))?;
+        Ok(BlockingOxidrop { runtime, oxidrop })
+    }
+
+    /// The async instance underneath, for what isn't wrapped here. Its
+    /// async methods must be run on [`runtime`](Self::runtime).
+    pub fn as_async(&self) -> &Oxidrop {
+        &self.oxidrop
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.runtime
+    }
+
+    pub fn advertise(&self) -> Result<()> {
+        self.runtime.block_on(self.oxidrop.advertise())
+    }
+
+    pub fn stop_advertising(&self) -> Result<()> {
+        self.runtime.block_on(self.oxidrop.stop_advertising())
+    }
+
+    /// Endpoints as they're discovered. Discovery runs until the iterator
+    /// is dropped.
+    pub fn discover_endpoints(&self) -> Result<BlockingIter<'_, Endpoint>> {
+        let stream = self.runtime.block_on(self.oxidrop.discover_endpoints())?;
+        Ok(self.iter(stream))
+    }
+
+    /// Inbound requests as they arrive, to answer with
+    /// [`accept_transfer`](Self::accept_transfer) or
+    /// [`reject_transfer`](Self::reject_transfer).
+    pub fn transfer_requests(&self) -> Result<BlockingIter<'_, TransferRequest>> {
+        let stream = {
+            let _guard = self.runtime.enter();
+            self.oxidrop.get_transfer_requests()?
+        };
+        Ok(self.iter(stream))
+    }
+
+    pub fn events(&self) -> BlockingIter<'_, OxidropEvent> {
+        self.iter(self.oxidrop.events())
+    }
+
+    pub fn accept_transfer(&self, request: &TransferRequest) -> Result<()> {
+        self.runtime.block_on(self.oxidrop.accept_transfer(request))
+    }
+
+    pub fn reject_transfer(&self, request: &TransferRequest) -> Result<()> {
+        self.runtime.block_on(self.oxidrop.reject_transfer(request))
+    }
+
+    pub fn reject_transfer_with_reason(
+        &self,
+        request: &TransferRequest,
+        reason: RejectReason,
+    ) -> Result<()> {
+        self.runtime
+            .block_on(self.oxidrop.reject_transfer_with_reason(request, reason))
+    }
+
+    pub fn send_files(
+        &self,
+        endpoint: &Endpoint,
+        files: impl IntoIterator<Item = File>,
+    ) -> Result<TransferHandle> {
+        self.runtime
+            .block_on(self.oxidrop.send_files(endpoint, files.into_iter()))
+    }
+
+    /// Blocks until an outbound transfer ends.
+    pub fn outcome(&self, handle: &TransferHandle) -> Result<TransferOutcome> {
+        self.runtime.block_on(self.oxidrop.outcome(handle))
+    }
+
+    pub fn cancel_transfer(&self, handle: &TransferHandle) -> Result<TransferState> {
+        self.runtime.block_on(self.oxidrop.cancel_transfer(handle))
+    }
+
+    fn iter<T>(&self, stream: impl Stream<Item = T> + Send + 'static) -> BlockingIter<'_, T> {
+        BlockingIter {
+            runtime: &self.runtime,
+            stream: Box::pin(stream),
+        }
+    }
+}
+
+/// Blocks on each item of one of [`BlockingOxidrop`]'s streams.
+pub struct BlockingIter<'a, T> {
+    runtime: &'a Runtime,
+    stream: Pin<Box<dyn Stream<Item = T> + Send>>,
+}
+
+impl<T> Iterator for BlockingIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// The SHA-256 digest of a file that was sent or received, for checking
+/// that both sides ended up with the same bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileDigest {
+    /// The name the file was sent under.
+    pub name: String,
+    pub sha256: [u8; 32],
+}
+
+impl FileDigest {
+    /// The digest as lowercase hex, the way `sha256sum` prints it.
+    pub fn hex(&self) -> String {
+        self.sha256
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, b| {
+                let _ = write!(hex, "{b:02x}");
+                hex
+            })
+    }
+}
+
+/// Digests `paths` on a blocking thread, skipping files that can't be read.
+pub(crate) async fn digest_files(paths: Vec<PathBuf>) -> Vec<FileDigest> {
+    tokio::task::spawn_blocking(move || {
+        paths
+            .iter()
+            .filter_map(|path| match digest_file(path) {
+                Ok(digest) => Some(digest),
+                Err(e) => {
+                    log::warn!("Failed to hash {}: {e}", path.display());
+                    None
+                }
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(fields(path = %path.display())))]
+fn digest_file(path: &Path) -> io::Result<FileDigest> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(FileDigest {
+        name: path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        sha256: hasher.finalize().into(),
+    })
+}
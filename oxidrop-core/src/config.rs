@@ -0,0 +1,226 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use crate::{
+    Config, Error, FileNamePolicy, OverwritePolicy, Result, SpacePolicy, StaticPeer, Visibility,
+};
+
+/// Builds a [`Config`], checking it before [`Oxidrop::new`](crate::Oxidrop::new)
+/// would run into problems while starting the service.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = Some(port);
+        self
+    }
+
+    pub fn download_dir(mut self, download_dir: impl Into<PathBuf>) -> Self {
+        self.config.download_dir = Some(download_dir.into());
+        self
+    }
+
+    pub fn send_only(mut self, send_only: bool) -> Self {
+        self.config.send_only = send_only;
+        self
+    }
+
+    pub fn blocked_senders(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.config.blocked_senders = names.into_iter().collect();
+        self
+    }
+
+    pub fn allowed_senders(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.config.allowed_senders = Some(names.into_iter().collect());
+        self
+    }
+
+    pub fn trust_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.trust_store = Some(path.into());
+        self
+    }
+
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.config.visibility = visibility;
+        self
+    }
+
+    pub fn overwrite_policy(mut self, policy: OverwritePolicy) -> Self {
+        self.config.overwrite_policy = policy;
+        self
+    }
+
+    pub fn file_names(mut self, policy: FileNamePolicy) -> Self {
+        self.config.file_names = policy;
+        self
+    }
+
+    pub fn space_policy(mut self, policy: SpacePolicy) -> Self {
+        self.config.space_policy = policy;
+        self
+    }
+
+    pub fn checksums(mut self, checksums: bool) -> Self {
+        self.config.checksums = checksums;
+        self
+    }
+
+    pub fn max_concurrent_sends(mut self, limit: usize) -> Self {
+        self.config.max_concurrent_sends = Some(limit);
+        self
+    }
+
+    pub fn consent_timeout(mut self, timeout: Duration) -> Self {
+        self.config.consent_timeout = Some(timeout);
+        self
+    }
+
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.config.stall_timeout = Some(timeout);
+        self
+    }
+
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.config.channel_capacity = Some(capacity);
+        self
+    }
+
+    pub fn static_peers(mut self, peers: impl IntoIterator<Item = StaticPeer>) -> Self {
+        self.config.static_peers = Some(peers.into_iter().collect());
+        self
+    }
+
+    #[cfg(feature = "history")]
+    pub fn history(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.history = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Config> {
+        let config = self.config;
+        if config.port == Some(0) {
+            return Err(Error::InvalidConfig(
+                "port must be between 1 and 65535".to_string(),
+            ));
+        }
+        if config.max_concurrent_sends == Some(0) {
+            return Err(Error::InvalidConfig(
+                "at least one send must be allowed at a time".to_string(),
+            ));
+        }
+        if config.consent_timeout == Some(Duration::ZERO)
+            || config.stall_timeout == Some(Duration::ZERO)
+        {
+            return Err(Error::InvalidConfig(
+                "timeouts must be longer than zero".to_string(),
+            ));
+        }
+        if config.channel_capacity == Some(0) {
+            return Err(Error::InvalidConfig(
+                "channel capacity must be at least 1".to_string(),
+            ));
+        }
+        if let Some(dir) = &config.download_dir {
+            match fs::metadata(dir) {
+                Ok(m) if !m.is_dir() => {
+                    return Err(Error::InvalidConfig(format!(
+                        "download directory {} isn't a directory",
+                        dir.display()
+                    )));
+                }
+                Ok(_) => {
+                    if let Err(e) = probe_writable(dir) {
+                        return Err(Error::InvalidConfig(format!(
+                            "download directory {} isn't writable: {e}",
+                            dir.display()
+                        )));
+                    }
+                }
+                Err(e) => {
+                    return Err(Error::InvalidConfig(format!(
+                        "download directory {}: {e}",
+                        dir.display()
+                    )));
+                }
+            }
+        }
+        if config.send_only && config.visibility != Visibility::Invisible {
+            return Err(Error::ReceivingDisabled);
+        }
+        Ok(config)
+    }
+}
+
+/// Creates and removes a file in `dir`, which unlike its permission bits
+/// accounts for ACLs, read-only mounts and who the process runs as.
+fn probe_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(".oxidrop-probe-{}", process::id()));
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)?;
+    fs::remove_file(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(builder: ConfigBuilder) -> String {
+        match builder.build() {
+            Err(Error::InvalidConfig(reason)) => reason,
+            other => panic!("expected an invalid config, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        assert!(Config::builder().build().is_ok());
+    }
+
+    #[test]
+    fn rejects_zero_values() {
+        assert!(invalid(Config::builder().port(0)).contains("port"));
+        assert!(invalid(Config::builder().max_concurrent_sends(0)).contains("send"));
+        assert!(invalid(Config::builder().consent_timeout(Duration::ZERO)).contains("timeouts"));
+        assert!(invalid(Config::builder().stall_timeout(Duration::ZERO)).contains("timeouts"));
+        assert!(invalid(Config::builder().channel_capacity(0)).contains("capacity"));
+    }
+
+    #[test]
+    fn rejects_download_dirs_that_cant_be_used() {
+        let dir = std::env::temp_dir().join(format!("oxidrop-config-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        fs::write(&file, "").unwrap();
+
+        assert!(invalid(Config::builder().download_dir(&file)).contains("isn't a directory"));
+        assert!(invalid(Config::builder().download_dir(dir.join("missing"))).contains("missing"));
+        assert!(Config::builder().download_dir(&dir).build().is_ok());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "probe left behind");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_visible_send_only_instances() {
+        let result = Config::builder()
+            .send_only(true)
+            .visibility(Visibility::Visible)
+            .build();
+        assert!(matches!(result, Err(Error::ReceivingDisabled)));
+        assert!(Config::builder().send_only(true).build().is_ok());
+    }
+}
@@ -0,0 +1,444 @@
+//! Realistic values for testing code built on this crate without a running
+//! service, e.g. rendering a device list or driving a transfer state machine.
+//!
+//! Ids are derived from names, so the same arguments always produce values
+//! that compare equal.
+//!
+//! [`loopback_pair`] goes further and starts two real instances that only
+//! know about each other, for integration tests.
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use rqs_lib::{
+    DeviceType, EndpointInfo, State,
+    channel::{ChannelDirection, ChannelMessage, TransferType},
+    hdl::info::{RemoteDeviceInfo, TransferMetadata},
+};
+
+use crate::{
+    Config, Endpoint, Oxidrop, OxidropEvent, Progress, Result, StaticPeer, TransferDirection,
+    TransferRequest, TransferState,
+    memory::{MemoryBackend, Network},
+};
+
+/// Name [`loopback_pair`]'s first instance is discovered under.
+pub const LOOPBACK_A: &str = "Loopback A";
+/// Name [`loopback_pair`]'s second instance is discovered under.
+pub const LOOPBACK_B: &str = "Loopback B";
+
+/// Starts two instances from `a` and `b` that discover each other through
+/// [`Config::static_peers`] rather than mDNS, and transfer through memory
+/// rather than sockets. Their ports and static peers are picked here; nothing
+/// touches the network, so any number of pairs can run at once.
+///
+/// Each one's [`Oxidrop::discover_endpoints`] yields only the other, named
+/// [`LOOPBACK_B`] and [`LOOPBACK_A`] respectively.
+pub async fn loopback_pair(a: Config, b: Config) -> Result<(Oxidrop, Oxidrop)> {
+    let network = Arc::new(Network::default());
+    let addr_a = SocketAddr::from((Ipv4Addr::LOCALHOST, 9301));
+    let addr_b = SocketAddr::from((Ipv4Addr::LOCALHOST, 9302));
+    let a = start_on(&network, a, (addr_a, LOOPBACK_A), (addr_b, LOOPBACK_B)).await?;
+    let b = start_on(&network, b, (addr_b, LOOPBACK_B), (addr_a, LOOPBACK_A)).await?;
+    Ok((a, b))
+}
+
+/// Starts an instance from `config` at `addr` on `network`, knowing only its
+/// peer.
+async fn start_on(
+    network: &Arc<Network>,
+    mut config: Config,
+    (addr, name): (SocketAddr, &'static str),
+    (peer_addr, peer_name): (SocketAddr, &str),
+) -> Result<Oxidrop> {
+    config.port = Some(addr.port());
+    config.static_peers = Some(vec![StaticPeer {
+        addr: peer_addr,
+        name: peer_name.to_string(),
+    }]);
+    let network = network.clone();
+    let oxidrop = Oxidrop::with_backend(
+        config,
+        Arc::new(move |config: &Config, _| {
+            Box::new(MemoryBackend::new(
+                network.clone(),
+                addr,
+                name,
+                config.download_dir.clone(),
+            ))
+        }),
+    )
+    .await?;
+    // Started hidden, so it takes transfers without announcing itself.
+    oxidrop.inner.ensure_started().await?;
+    Ok(oxidrop)
+}
+
+/// A phone called `name`, discovered on the local network.
+pub fn endpoint(name: &str) -> Endpoint {
+    endpoint_at(name, "192.168.1.23", 9300)
+}
+
+/// A phone called `name` announcing itself at `ip` and `port`.
+pub fn endpoint_at(name: &str, ip: &str, port: u16) -> Endpoint {
+    let id = id_for(name);
+    Endpoint(EndpointInfo {
+        fullname: format!("{id}._FC9F5ED42C8A._tcp.local."),
+        id,
+        name: Some(name.to_string()),
+        ip: Some(ip.to_string()),
+        port: Some(port.to_string()),
+        rtype: Some(DeviceType::Phone),
+        present: Some(true),
+    })
+}
+
+/// An inbound request from `sender` offering `files`, waiting for consent.
+pub fn transfer_request(sender: &str, files: &[&str]) -> TransferRequest {
+    TransferRequest(inbound_message(
+        sender,
+        TransferMetadata {
+            files: Some(files.iter().map(|f| f.to_string()).collect()),
+            total_bytes: 1024 * 1024 * files.len() as u64,
+            ..metadata(&format!("{sender}:{}", files.join("/")))
+        },
+    ))
+}
+
+/// An inbound request from `sender` offering a piece of text.
+pub fn text_request(sender: &str, text: &str) -> TransferRequest {
+    TransferRequest(inbound_message(
+        sender,
+        TransferMetadata {
+            text_description: Some(text.to_string()),
+            total_bytes: text.len() as u64,
+            ..metadata(&format!("{sender}:{text}"))
+        },
+    ))
+}
+
+/// The event announcing that transfer `id` moved to `state`.
+pub fn state_changed(id: &str, direction: TransferDirection, state: TransferState) -> OxidropEvent {
+    OxidropEvent::TransferStateChanged {
+        id: id.to_string(),
+        direction,
+        state,
+    }
+}
+
+/// The event announcing that `transferred` of `total` bytes of transfer `id`
+/// have been moved.
+pub fn progress(
+    id: &str,
+    direction: TransferDirection,
+    transferred: u64,
+    total: u64,
+) -> OxidropEvent {
+    OxidropEvent::TransferProgress {
+        id: id.to_string(),
+        direction,
+        progress: Progress {
+            transferred,
+            total,
+            file_index: None,
+        },
+    }
+}
+
+fn inbound_message(sender: &str, meta: TransferMetadata) -> ChannelMessage {
+    ChannelMessage {
+        id: meta.id.clone(),
+        direction: ChannelDirection::LibToFront,
+        action: None,
+        rtype: Some(TransferType::Inbound),
+        state: Some(State::WaitingForUserConsent),
+        meta: Some(TransferMetadata {
+            source: Some(RemoteDeviceInfo {
+                name: sender.to_string(),
+                device_type: DeviceType::Phone,
+            }),
+            ..meta
+        }),
+    }
+}
+
+fn metadata(key: &str) -> TransferMetadata {
+    TransferMetadata {
+        id: format!("transfer-{}", id_for(key)),
+        pin_code: Some("1234".to_string()),
+        ..Default::default()
+    }
+}
+
+/// A four character endpoint id, like the ones Quick Share uses.
+fn id_for(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(u32::from(b)));
+    format!("{:04X}", hash & 0xFFFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        process,
+        sync::atomic::Ordering,
+        time::Duration,
+    };
+
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{Decision, Error, File, TransferHandle, TransferOutcome};
+
+    /// A scratch directory for `test` holding `outbox/notes.txt`.
+    fn scratch(test: &str) -> PathBuf {
+        let scratch =
+            std::env::temp_dir().join(format!("oxidrop-fixtures-{test}-{}", process::id()));
+        fs::create_dir_all(scratch.join("outbox")).unwrap();
+        fs::create_dir_all(scratch.join("inbox")).unwrap();
+        fs::write(scratch.join("outbox/notes.txt"), "hello over memory").unwrap();
+        scratch
+    }
+
+    /// Sends `scratch`'s notes from `a` to `b`, and returns the transfer as
+    /// both see it.
+    async fn offer(a: &Oxidrop, b: &Oxidrop, scratch: &Path) -> (TransferHandle, TransferRequest) {
+        let mut requests = b.get_transfer_requests().unwrap();
+        let endpoint = a.discover_endpoints().await.unwrap().next().await.unwrap();
+        let handle = a
+            .send_files(
+                &endpoint,
+                [File::new(scratch.join("outbox/notes.txt"))].into_iter(),
+            )
+            .await
+            .unwrap();
+        (handle, requests.next().await.unwrap())
+    }
+
+    /// The state `request` ends in on `b`.
+    async fn final_state(b: &Oxidrop, request: &TransferRequest) -> TransferState {
+        b.watch(request).fold(None, |_, s| Some(s)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_files_across_a_loopback_pair() {
+        let scratch = scratch("send");
+        let (a, b) = loopback_pair(
+            Config::default(),
+            Config {
+                download_dir: Some(scratch.join("inbox")),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let mut requests = b.get_transfer_requests().unwrap();
+        let endpoint = a.discover_endpoints().await.unwrap().next().await.unwrap();
+        assert_eq!(endpoint.name(), LOOPBACK_B);
+
+        let handle = a
+            .send_files(
+                &endpoint,
+                [File::new(scratch.join("outbox/notes.txt"))].into_iter(),
+            )
+            .await
+            .unwrap();
+        let request = requests.next().await.unwrap();
+        assert_eq!(request.sender_name(), LOOPBACK_A);
+        assert_eq!(request.files(), ["notes.txt"]);
+        b.accept_transfer(&request).await.unwrap();
+
+        assert_eq!(a.outcome(&handle).await.unwrap(), TransferOutcome::Finished);
+        assert_eq!(
+            fs::read_to_string(scratch.join("inbox/notes.txt")).unwrap(),
+            "hello over memory"
+        );
+        assert_eq!(a.transfer_progress(handle.id()).next().await, None);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn sender_gives_up_on_a_receiver_that_never_answers() {
+        let scratch = scratch("outbound-consent");
+        let (a, b) = loopback_pair(
+            Config {
+                consent_timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+            Config::default(),
+        )
+        .await
+        .unwrap();
+        let (handle, request) = offer(&a, &b, &scratch).await;
+
+        assert_eq!(a.outcome(&handle).await.unwrap(), TransferOutcome::TimedOut);
+        assert_eq!(final_state(&b, &request).await, TransferState::Cancelled);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn receiver_declines_a_request_left_unanswered() {
+        let scratch = scratch("inbound-consent");
+        let (a, b) = loopback_pair(
+            Config::default(),
+            Config {
+                consent_timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let (handle, request) = offer(&a, &b, &scratch).await;
+
+        assert_eq!(final_state(&b, &request).await, TransferState::TimedOut);
+        assert_eq!(a.outcome(&handle).await.unwrap(), TransferOutcome::Declined);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn receiver_cancels_a_stalled_transfer() {
+        let scratch = scratch("stall");
+        let network = Arc::new(Network::default());
+        network.stall.store(true, Ordering::Relaxed);
+        let addr_a = SocketAddr::from((Ipv4Addr::LOCALHOST, 9301));
+        let addr_b = SocketAddr::from((Ipv4Addr::LOCALHOST, 9302));
+        let a = start_on(
+            &network,
+            Config::default(),
+            (addr_a, LOOPBACK_A),
+            (addr_b, LOOPBACK_B),
+        )
+        .await
+        .unwrap();
+        let b = start_on(
+            &network,
+            Config {
+                download_dir: Some(scratch.join("inbox")),
+                stall_timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+            (addr_b, LOOPBACK_B),
+            (addr_a, LOOPBACK_A),
+        )
+        .await
+        .unwrap();
+        let (handle, request) = offer(&a, &b, &scratch).await;
+        b.accept_transfer(&request).await.unwrap();
+
+        assert_eq!(final_state(&b, &request).await, TransferState::TimedOut);
+        assert_eq!(
+            a.outcome(&handle).await.unwrap(),
+            TransferOutcome::Cancelled
+        );
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn outcome_of_an_unknown_transfer_fails() {
+        let (a, _b) = loopback_pair(Config::default(), Config::default())
+            .await
+            .unwrap();
+        let handle = TransferHandle {
+            id: "elsewhere-0".to_string(),
+        };
+        assert!(matches!(
+            a.outcome(&handle).await,
+            Err(Error::UnknownTransfer(_))
+        ));
+        assert_eq!(a.transfer_progress(handle.id()).next().await, None);
+    }
+
+    #[tokio::test]
+    async fn forgets_transfers_that_ended_long_ago() {
+        let scratch = scratch("retire");
+        let (a, b) = loopback_pair(
+            Config::default(),
+            Config {
+                download_dir: Some(scratch.join("inbox")),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let (handle, request) = offer(&a, &b, &scratch).await;
+        b.accept_transfer(&request).await.unwrap();
+        assert_eq!(a.outcome(&handle).await.unwrap(), TransferOutcome::Finished);
+        assert_eq!(final_state(&b, &request).await, TransferState::Done);
+
+        for n in 0..crate::MAX_ENDED_TRANSFERS {
+            a.inner.retire(&format!("later-{n}"));
+            b.inner.retire(&format!("later-{n}"));
+        }
+        assert!(matches!(
+            a.outcome(&handle).await,
+            Err(Error::UnknownTransfer(_))
+        ));
+        assert_eq!(b.watch(&request).next().await, None);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn remembers_only_names_the_user_accepts() {
+        let scratch = scratch("names");
+        let (a, b) = loopback_pair(
+            Config::default(),
+            Config {
+                download_dir: Some(scratch.join("inbox")),
+                trust_store: Some(scratch.join("names")),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let names = b.trust_store().unwrap();
+
+        b.set_accept_policy(|_| Decision::Accept);
+        let endpoint = a.discover_endpoints().await.unwrap().next().await.unwrap();
+        let handle = a
+            .send_files(
+                &endpoint,
+                [File::new(scratch.join("outbox/notes.txt"))].into_iter(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(a.outcome(&handle).await.unwrap(), TransferOutcome::Finished);
+        assert!(names.devices().is_empty());
+
+        b.set_accept_policy(|_| Decision::Ask);
+        b.set_download_dir(Some(scratch.join("inbox-again")))
+            .await
+            .unwrap();
+        let (handle, request) = offer(&a, &b, &scratch).await;
+        b.accept_transfer(&request).await.unwrap();
+        assert_eq!(a.outcome(&handle).await.unwrap(), TransferOutcome::Finished);
+        assert_eq!(names.devices(), [LOOPBACK_A]);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+
+    #[tokio::test]
+    async fn stages_renamed_files_once_for_every_receiver() {
+        let scratch = scratch("many");
+        let (a, _b) = loopback_pair(Config::default(), Config::default())
+            .await
+            .unwrap();
+        let endpoint = a.discover_endpoints().await.unwrap().next().await.unwrap();
+        let file = File {
+            display_name: Some("renamed.txt".to_string()),
+            ..File::new(scratch.join("outbox/notes.txt"))
+        };
+        let handles = a.send_files_to_many(&[&endpoint, &endpoint], [file]).await;
+
+        let outgoing = a.inner.outgoing.lock().unwrap();
+        let [first, second] =
+            [&handles[0], &handles[1]].map(|h| outgoing[h.as_ref().unwrap().id()].files.clone());
+        assert!(first[0].ends_with("renamed.txt"));
+        assert_eq!(first, second);
+        drop(outgoing);
+        let _ = fs::remove_dir_all(&scratch);
+    }
+}
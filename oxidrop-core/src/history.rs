@@ -0,0 +1,143 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, RejectReason, Result, TransferDirection, TransferState};
+
+/// A transfer that ended, as recorded in [`Config::history`](crate::Config::history).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub direction: TransferDirection,
+    /// Name of the other device.
+    pub peer: String,
+    pub files: Vec<HistoryFile>,
+    /// The final state the transfer ended in.
+    pub state: TransferState,
+    pub started: SystemTime,
+    pub ended: SystemTime,
+    /// Why the transfer was rejected, if it was rejected here.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<RejectReason>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryFile {
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// Which entries [`Oxidrop::history`](crate::Oxidrop::history) returns. The
+/// default matches every entry.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryFilter {
+    pub direction: Option<TransferDirection>,
+    pub peer: Option<String>,
+    pub state: Option<TransferState>,
+    /// Only transfers that ended at or after this time.
+    pub since: Option<SystemTime>,
+    /// Only the most recent entries, at most this many.
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.direction.is_none_or(|d| d == entry.direction)
+            && self.peer.as_ref().is_none_or(|p| *p == entry.peer)
+            && self.state.is_none_or(|s| s == entry.state)
+            && self.since.is_none_or(|t| entry.ended >= t)
+    }
+}
+
+/// Appends ended transfers to a file with one JSON entry per line.
+pub(crate) struct History {
+    path: PathBuf,
+    /// When transfers still running started, and who they're with.
+    started: Mutex<HashMap<String, (SystemTime, String)>>,
+}
+
+impl History {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        History {
+            path,
+            started: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn start(&self, id: &str, peer: String) {
+        self.started
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.to_string(), (SystemTime::now(), peer));
+    }
+
+    pub(crate) fn finish(
+        &self,
+        id: &str,
+        direction: TransferDirection,
+        state: TransferState,
+        files: Vec<HistoryFile>,
+        reason: Option<RejectReason>,
+    ) -> Result<()> {
+        let ended = SystemTime::now();
+        let (started, peer) = self
+            .started
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(id)
+            .unwrap_or_else(|| (ended, id.to_string()));
+        let entry = HistoryEntry {
+            id: id.to_string(),
+            direction,
+            peer,
+            files,
+            state,
+            started,
+            ended,
+            reason,
+        };
+        let mut line = serde_json::to_string(&entry).map_err(|e| Error::Other(Box::new(e)))?;
+        line.push('\n');
+        self.path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+            })
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .map_err(|e| Error::Other(Box::new(e)))
+    }
+
+    /// Entries matching `filter`, oldest first. Lines that can't be read are
+    /// skipped.
+    pub(crate) fn query(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(Error::Other(Box::new(e))),
+        };
+        let mut entries = vec![];
+        for line in io::BufReader::new(file).lines() {
+            let line = line.map_err(|e| Error::Other(Box::new(e)))?;
+            match serde_json::from_str::<HistoryEntry>(&line) {
+                Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(e) => log::warn!("Skipping unreadable history entry: {e}"),
+            }
+        }
+        if let Some(limit) = filter.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::sanitize;
+
+/// A file of an inbound transfer, which may still be being written.
+///
+/// Files are written front to back, so the bytes up to
+/// [`valid_len`](Self::valid_len) can be read while the rest is arriving,
+/// e.g. to start playing a video before it's complete.
+#[derive(Clone, Debug)]
+pub struct IncomingFile {
+    pub(crate) path: PathBuf,
+    pub(crate) complete: bool,
+}
+
+impl IncomingFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the whole file had been received when it was looked up.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// How many bytes from the start of the file have been received so far.
+    pub fn valid_len(&self) -> io::Result<u64> {
+        fs::metadata(&self.path).map(|m| m.len())
+    }
+
+    /// Opens the file for reading, stopping at the bytes received so far. Call
+    /// again to read data that arrived later.
+    pub fn open(&self) -> io::Result<io::Take<fs::File>> {
+        let file = fs::File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        Ok(file.take(len))
+    }
+}
+
+/// What to do when a received file has the same name as one already in the
+/// download directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverwritePolicy {
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing file and save the received one as `name (1).ext`.
+    #[default]
+    RenameWithSuffix,
+    /// Keep the existing file and drop the received one.
+    Skip,
+    /// Refuse to accept the transfer.
+    Fail,
+}
+
+/// Where the files of an inbound transfer are being saved.
+#[derive(Clone, Debug)]
+pub(crate) struct IncomingTransfer {
+    pub(crate) dir: PathBuf,
+    pub(crate) files: Vec<String>,
+    /// Existing files that received ones will replace, with where they were
+    /// copied. The copies are put back once the transfer ends.
+    pub(crate) set_aside: Vec<(PathBuf, PathBuf)>,
+    /// Bytes of the transfer written so far, as last reported.
+    pub(crate) received: u64,
+}
+
+/// How a received file that collided with an existing one was handled.
+pub(crate) struct Conflict {
+    pub(crate) path: PathBuf,
+    pub(crate) saved_as: Option<PathBuf>,
+}
+
+impl IncomingTransfer {
+    pub(crate) fn new(dir: PathBuf, files: Vec<String>) -> Self {
+        IncomingTransfer {
+            dir,
+            files,
+            set_aside: vec![],
+            received: 0,
+        }
+    }
+
+    /// Copies existing files that received ones will replace before the
+    /// transfer is accepted, leaving them in place, so nothing is lost if the
+    /// transfer fails. Returns the first conflicting path if the policy is to
+    /// fail.
+    pub(crate) fn prepare(&mut self, policy: OverwritePolicy) -> Result<(), PathBuf> {
+        let conflicts = self
+            .files
+            .iter()
+            .map(|name| self.dir.join(name))
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+        if policy == OverwritePolicy::Fail
+            && let Some(path) = conflicts.first()
+        {
+            return Err(path.clone());
+        }
+
+        for path in conflicts {
+            let mut name = OsString::from(".");
+            name.push(path.file_name().unwrap_or_default());
+            name.push(".oxidrop-kept");
+            let aside = path.with_file_name(name);
+            match fs::copy(&path, &aside) {
+                Ok(_) => self.set_aside.push((path, aside)),
+                Err(e) => log::warn!("Failed to copy {} aside: {e}", path.display()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts the files copied aside by [`prepare`](Self::prepare) back
+    /// according to `policy`, renaming received files that are kept alongside
+    /// them. A transfer that didn't finish leaves things as they were.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub(crate) fn finish(&mut self, done: bool, policy: OverwritePolicy) -> Vec<Conflict> {
+        let mut conflicts = vec![];
+        for (path, aside) in &self.set_aside {
+            let result = if !done {
+                // A partial file may or may not have been written yet.
+                let _ = fs::remove_file(path);
+                fs::rename(aside, path).map(|()| None)
+            } else {
+                match policy {
+                    OverwritePolicy::Overwrite | OverwritePolicy::Fail => {
+                        fs::remove_file(aside).map(|()| Some(path.clone()))
+                    }
+                    OverwritePolicy::Skip => fs::rename(aside, path).map(|()| None),
+                    OverwritePolicy::RenameWithSuffix => {
+                        let renamed = unused_path(path);
+                        fs::rename(path, &renamed)
+                            .and_then(|()| fs::rename(aside, path))
+                            .map(|()| Some(renamed))
+                    }
+                }
+            };
+            match result {
+                Ok(saved_as) if done => {
+                    if let Some(name) = saved_as.as_ref().and_then(|p| p.file_name())
+                        && let Some(file) = self
+                            .files
+                            .iter_mut()
+                            .find(|f| self.dir.join(f.as_str()) == *path)
+                    {
+                        *file = name.to_string_lossy().into_owned();
+                    }
+                    conflicts.push(Conflict {
+                        path: path.clone(),
+                        saved_as,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Failed to restore {}: {e}", path.display()),
+            }
+        }
+        conflicts
+    }
+
+    /// Renames received files whose names aren't safe everywhere. Returns
+    /// the original names with the paths the files were moved to.
+    pub(crate) fn sanitize_names(&mut self) -> Vec<(String, PathBuf)> {
+        let mut renamed = vec![];
+        for file in &mut self.files {
+            let Some(safe) = sanitize::sanitize(file) else {
+                continue;
+            };
+            let path = self.dir.join(file.as_str());
+            let target = unused_path(&self.dir.join(&safe));
+            match fs::rename(&path, &target) {
+                Ok(()) => {
+                    let original = std::mem::replace(
+                        file,
+                        target
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .into_owned(),
+                    );
+                    renamed.push((original, target));
+                }
+                Err(e) => log::warn!("Failed to rename {}: {e}", path.display()),
+            }
+        }
+        renamed
+    }
+
+    /// Whether `path` is an existing file that was copied aside, rather than
+    /// one this transfer writes.
+    pub(crate) fn was_set_aside(&self, path: &Path) -> bool {
+        self.set_aside.iter().any(|(p, _)| p == path)
+    }
+
+    /// The files that have been started, in the order they're received. A
+    /// file is complete once the next one has been started, or once the
+    /// transfer is `done`.
+    ///
+    /// Files are told apart from ones already in the download directory by
+    /// the bytes received: a file has been started once more has arrived than
+    /// the files before it hold.
+    pub(crate) fn files(&self, done: bool) -> Vec<IncomingFile> {
+        let mut started = vec![];
+        let mut before = 0;
+        for path in self.files.iter().map(|name| self.dir.join(name)) {
+            if !done && self.received <= before {
+                break;
+            }
+            // Earlier files are complete, so what's on disk is what arrived.
+            before += fs::metadata(&path).map_or(0, |m| m.len());
+            started.push(path);
+        }
+        let count = started.len();
+        started
+            .into_iter()
+            .enumerate()
+            .map(|(i, path)| IncomingFile {
+                path,
+                complete: done || i + 1 < count,
+            })
+            .collect()
+    }
+}
+
+/// `path` if nothing exists there, otherwise `path` with the first ` (n)`
+/// suffix that doesn't name an existing file.
+fn unused_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){extension}")))
+        .find(|p| !p.exists())
+        .expect("some suffix is unused")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn download_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("oxidrop-incoming-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keeps_existing_files_in_place_and_renames_the_received_one() {
+        let dir = download_dir("rename");
+        fs::write(dir.join("photo.jpg"), "old").unwrap();
+        let mut transfer = IncomingTransfer::new(dir.clone(), vec!["photo.jpg".to_string()]);
+
+        transfer.prepare(OverwritePolicy::RenameWithSuffix).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("photo.jpg")).unwrap(), "old");
+
+        fs::write(dir.join("photo.jpg"), "new").unwrap();
+        let conflicts = transfer.finish(true, OverwritePolicy::RenameWithSuffix);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].saved_as, Some(dir.join("photo (1).jpg")));
+        assert_eq!(fs::read_to_string(dir.join("photo.jpg")).unwrap(), "old");
+        assert_eq!(
+            fs::read_to_string(dir.join("photo (1).jpg")).unwrap(),
+            "new"
+        );
+        assert_eq!(transfer.files, ["photo (1).jpg"]);
+        assert!(!dir.join(".photo.jpg.oxidrop-kept").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn restores_existing_files_when_the_transfer_fails() {
+        let dir = download_dir("failed");
+        fs::write(dir.join("notes.txt"), "old").unwrap();
+        let mut transfer = IncomingTransfer::new(dir.clone(), vec!["notes.txt".to_string()]);
+
+        transfer.prepare(OverwritePolicy::Overwrite).unwrap();
+        fs::write(dir.join("notes.txt"), "partial").unwrap();
+        assert!(
+            transfer
+                .finish(false, OverwritePolicy::Overwrite)
+                .is_empty()
+        );
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "old");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fail_policy_reports_the_conflict() {
+        let dir = download_dir("fail");
+        fs::write(dir.join("notes.txt"), "old").unwrap();
+        let mut transfer = IncomingTransfer::new(dir.clone(), vec!["notes.txt".to_string()]);
+
+        assert_eq!(
+            transfer.prepare(OverwritePolicy::Fail),
+            Err(dir.join("notes.txt"))
+        );
+        assert!(transfer.set_aside.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn existing_files_only_count_once_bytes_arrive() {
+        let dir = download_dir("started");
+        fs::write(dir.join("a.txt"), "old contents").unwrap();
+        fs::write(dir.join("b.txt"), "old").unwrap();
+        let mut transfer =
+            IncomingTransfer::new(dir.clone(), vec!["a.txt".to_string(), "b.txt".to_string()]);
+        transfer.prepare(OverwritePolicy::Overwrite).unwrap();
+        assert!(transfer.files(false).is_empty());
+
+        fs::write(dir.join("a.txt"), "ne").unwrap();
+        transfer.received = 2;
+        let files = transfer.files(false);
+        assert_eq!(files.len(), 1);
+        assert!(!files[0].is_complete());
+
+        fs::write(dir.join("a.txt"), "new").unwrap();
+        fs::write(dir.join("b.txt"), "n").unwrap();
+        transfer.received = 4;
+        let files = transfer.files(false);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].is_complete() && !files[1].is_complete());
+        assert!(transfer.files(true).iter().all(IncomingFile::is_complete));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
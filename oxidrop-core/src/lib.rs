@@ -0,0 +1,3065 @@
+mod backend;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod checksum;
+mod config;
+#[cfg(any(test, feature = "testing"))]
+pub mod fixtures;
+#[cfg(feature = "history")]
+mod history;
+mod incoming;
+#[cfg(any(test, feature = "testing"))]
+mod memory;
+mod metrics;
+mod observer;
+#[cfg(feature = "proto-debug")]
+mod proto_debug;
+mod queue;
+#[cfg(feature = "unstable-raw")]
+pub mod raw;
+mod sanitize;
+#[cfg(feature = "serde")]
+mod serialize;
+mod space;
+mod staging;
+mod transfer;
+mod trust;
+
+pub use checksum::FileDigest;
+pub use config::ConfigBuilder;
+#[cfg(feature = "history")]
+pub use history::{HistoryEntry, HistoryFile, HistoryFilter};
+pub use incoming::{IncomingFile, OverwritePolicy};
+pub use metrics::Metrics;
+pub use observer::{ObserverHandle, TransferObserver};
+pub use sanitize::FileNamePolicy;
+pub use space::SpacePolicy;
+pub use transfer::{
+    Payload, Progress, RejectReason, TransferDirection, TransferOutcome, TransferState,
+};
+pub use trust::TrustStore;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use pin_project::{pin_project, pinned_drop};
+use rqs_lib::{
+    EndpointInfo, OutboundPayload, SendInfo,
+    channel::{ChannelAction, ChannelMessage},
+};
+use thiserror::Error;
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, mpsc, oneshot},
+    time::{sleep, timeout},
+};
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{BroadcastStream, ReceiverStream, errors::BroadcastStreamRecvError},
+};
+
+use crate::backend::ShareBackend;
+
+#[derive(Clone, Debug)]
+pub struct TransferRequest(ChannelMessage);
+
+#[derive(Clone)]
+pub struct Endpoint(EndpointInfo);
+
+/// A device configured in [`Config::static_peers`] rather than discovered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticPeer {
+    pub addr: SocketAddr,
+    pub name: String,
+}
+
+/// A change to the set of endpoints, from [`Oxidrop::discover_endpoint_updates`].
+#[derive(Clone, Debug)]
+pub enum EndpointEvent {
+    Added(Endpoint),
+    /// A known endpoint announced itself again with a different name or
+    /// address.
+    Updated(Endpoint),
+    /// An endpoint said goodbye or hasn't been heard from in a while.
+    Removed(Endpoint),
+}
+
+/// The kind of device an [`Endpoint`] says it is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceType {
+    #[default]
+    Unknown,
+    Phone,
+    Tablet,
+    Laptop,
+}
+
+/// A transfer that hasn't ended yet, from [`Oxidrop::active_transfers`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActiveTransfer {
+    pub id: String,
+    pub direction: TransferDirection,
+    /// Name of the other device.
+    pub peer: String,
+    pub state: TransferState,
+    /// The latest progress, once data has started moving.
+    pub progress: Option<Progress>,
+}
+
+/// A send waiting in the queue, from [`Oxidrop::pending_sends`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PendingSend {
+    pub handle: TransferHandle,
+    /// Name of the device it goes to.
+    pub receiver: String,
+    pub priority: i32,
+}
+
+/// Identifies an outbound transfer started by [`Oxidrop::send_files`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransferHandle {
+    id: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct File {
+    pub path: PathBuf,
+    /// The name the receiving device sees instead of `path`'s file name.
+    pub display_name: Option<String>,
+    /// The directory `path` is considered relative to, for keeping subfolder
+    /// structure. `rqs_lib` doesn't send folders yet, so the file still
+    /// arrives under its name alone.
+    pub relative_to: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// The TCP port to listen on. If unset, a free one is picked when the
+    /// instance is created; see [`Oxidrop::bound_port`].
+    pub port: Option<u16>,
+    /// Where received files are saved; `rqs_lib` picks a default if unset.
+    pub download_dir: Option<PathBuf>,
+    /// Never advertise and reject every inbound transfer. `rqs_lib` still
+    /// binds its listening port, but nothing sent to it is accepted.
+    pub send_only: bool,
+    /// Names of devices whose requests are rejected without asking.
+    pub blocked_senders: Vec<String>,
+    /// If set, requests from devices not named here are rejected without
+    /// asking. Quick Share doesn't tell receivers the sender's device id, so
+    /// senders are told apart by name.
+    pub allowed_senders: Option<Vec<String>>,
+    /// Where to remember the names of devices transfers were accepted from.
+    /// See [`TrustStore`].
+    pub trust_store: Option<PathBuf>,
+    /// Whether nearby devices can see this one once the service starts.
+    pub visibility: Visibility,
+    /// What to do with received files named like ones already in the
+    /// download directory.
+    pub overwrite_policy: OverwritePolicy,
+    /// What to do with received files whose names aren't safe to use on
+    /// every platform.
+    pub file_names: FileNamePolicy,
+    /// What to do with requests that don't fit in the download directory.
+    pub space_policy: SpacePolicy,
+    /// Hash the files of every finished transfer and announce the digests
+    /// with [`OxidropEvent::TransferChecksums`].
+    pub checksums: bool,
+    /// How many outbound transfers may run at once. Further sends wait in a
+    /// queue; see [`Oxidrop::pending_sends`].
+    pub max_concurrent_sends: Option<usize>,
+    /// Where to record every transfer once it ends, one JSON object per
+    /// line. See [`Oxidrop::history`].
+    #[cfg(feature = "history")]
+    pub history: Option<PathBuf>,
+    /// How long a transfer may wait for the receiver to accept it before
+    /// it's given up on and ends as [`TransferState::TimedOut`]. Inbound
+    /// requests left unanswered that long are rejected.
+    pub consent_timeout: Option<Duration>,
+    /// How long an accepted transfer may go without moving any bytes before
+    /// it's cancelled and ends as [`TransferState::TimedOut`].
+    pub stall_timeout: Option<Duration>,
+    /// How many events, messages from `rqs_lib` and discovered endpoints are
+    /// kept for subscribers that fall behind, after which they miss some.
+    /// Defaults to 10.
+    pub channel_capacity: Option<usize>,
+    /// If set, mDNS discovery is never started and
+    /// [`Oxidrop::discover_endpoints`] yields these devices instead, for
+    /// networks that filter multicast.
+    pub static_peers: Option<Vec<StaticPeer>>,
+}
+
+impl Config {
+    fn allows_sender(&self, name: &str) -> bool {
+        !self.blocked_senders.iter().any(|n| n == name)
+            && self
+                .allowed_senders
+                .as_ref()
+                .is_none_or(|allowed| allowed.iter().any(|n| n == name))
+    }
+}
+
+/// Whether nearby devices can see this one and send to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Visibility {
+    Visible,
+    #[default]
+    Invisible,
+    /// Visible for a while, after which `rqs_lib` hides the device again
+    /// without telling.
+    Temporarily,
+}
+
+/// What an accept policy decides to do with an inbound request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Accept,
+    Reject,
+    /// Leave it to whoever answers requests from
+    /// [`Oxidrop::get_transfer_requests`].
+    Ask,
+}
+
+type AcceptPolicy = Arc<dyn Fn(&TransferRequest) -> Decision + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServiceStatus {
+    /// Nothing has needed the service yet, so it hasn't been started.
+    Idle,
+    Running,
+    Restarting,
+    Failed,
+}
+
+/// A snapshot of the instance's health, suitable for readiness probes.
+#[derive(Clone, Debug)]
+pub struct HealthReport {
+    pub status: ServiceStatus,
+    /// Whether the service's event loop is still accepting work.
+    pub alive: bool,
+    /// The listening port, picked when the instance was created if the
+    /// config didn't set one.
+    pub port: Option<u16>,
+    /// Whether the mDNS responder is announcing this device.
+    pub advertising: bool,
+    /// Whether mDNS discovery of other devices is running.
+    pub discovering: bool,
+    pub last_error: Option<String>,
+}
+
+/// Everything that happens in an instance, in one stream from
+/// [`Oxidrop::events`].
+///
+/// Endpoint events only flow while something is discovering endpoints, since
+/// discovery is off otherwise.
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum OxidropEvent {
+    /// An endpoint was found that wasn't known before.
+    EndpointDiscovered(Endpoint),
+    /// A send target was found again under a different address after the
+    /// previous one stopped answering.
+    EndpointAddressChanged {
+        previous: Endpoint,
+        current: Endpoint,
+    },
+    /// This device was shown or hidden with [`Oxidrop::set_visibility`].
+    VisibilityChanged(Visibility),
+    /// Settings were changed with [`Oxidrop::reload_config`]. `needs_restart`
+    /// names the ones only a new instance picks up, which were left as they
+    /// were.
+    ConfigReloaded { needs_restart: Vec<String> },
+    /// The background service stopped unexpectedly and was started again.
+    ServiceRestarted { attempts: u32 },
+    /// The background service stopped unexpectedly and couldn't be restarted.
+    ServiceFailed(String),
+    /// A known endpoint announced itself again with a different name or
+    /// address.
+    EndpointUpdated(Endpoint),
+    /// A discovered endpoint stopped announcing itself.
+    EndpointLost(Endpoint),
+    /// A device asked to send something and is waiting for consent.
+    TransferRequested(TransferRequest),
+    /// A request was rejected because [`Config::blocked_senders`] or
+    /// [`Config::allowed_senders`] doesn't let its sender through.
+    TransferRequestBlocked(TransferRequest),
+    /// A request is larger than the free space in the download directory.
+    /// It's rejected if [`Config::space_policy`] says so, and asked about
+    /// as usual otherwise.
+    InsufficientSpace {
+        request: TransferRequest,
+        available: u64,
+    },
+    /// A pending inbound request ended before it was answered, usually
+    /// because the sender cancelled it.
+    TransferRequestWithdrawn(TransferRequest),
+    /// An inbound request was rejected, by
+    /// [`Oxidrop::reject_transfer_with_reason`] or because of the config.
+    TransferRejected { id: String, reason: RejectReason },
+    /// More of a transfer's payload was sent or received.
+    TransferProgress {
+        id: String,
+        direction: TransferDirection,
+        progress: Progress,
+    },
+    /// A transfer moved to a new [`TransferState`].
+    TransferStateChanged {
+        id: String,
+        direction: TransferDirection,
+        state: TransferState,
+    },
+    /// The text of an inbound transfer that carried text rather than files,
+    /// just before its [`OxidropEvent::TransferFinished`].
+    TextReceived { id: String, payload: Payload },
+    /// A received file was named like one already in the download directory,
+    /// and was handled by [`Config::overwrite_policy`]. `saved_as` is where
+    /// the received file ended up, or `None` if it was dropped.
+    FileConflict {
+        id: String,
+        path: PathBuf,
+        policy: OverwritePolicy,
+        saved_as: Option<PathBuf>,
+    },
+    /// A received file was renamed by [`FileNamePolicy::Sanitize`] because
+    /// its name wasn't safe to use everywhere.
+    FileRenamed {
+        id: String,
+        name: String,
+        path: PathBuf,
+    },
+    /// A transfer completed, following its change to [`TransferState::Done`].
+    TransferFinished {
+        id: String,
+        direction: TransferDirection,
+    },
+    /// The SHA-256 digests of a finished transfer's files, following its
+    /// [`OxidropEvent::TransferFinished`] if [`Config::checksums`] is set.
+    TransferChecksums {
+        id: String,
+        direction: TransferDirection,
+        digests: Vec<FileDigest>,
+    },
+    /// The connection was lost mid-transfer, following its change to
+    /// [`TransferState::Failed`].
+    TransferFailed {
+        id: String,
+        direction: TransferDirection,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Internal state corrupted")]
+    CorruptedState,
+    #[error("Endpoint {0} is unreachable")]
+    EndpointUnreachable(String),
+    #[error("Receiving is disabled in send-only mode")]
+    ReceivingDisabled,
+    #[error("Invalid file name: {0:?}")]
+    InvalidFileName(String),
+    #[error("No transfer with id {0}")]
+    UnknownTransfer(String),
+    #[error("{} already exists", .0.display())]
+    FileExists(PathBuf),
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("Transfer {0} can't be resumed")]
+    CannotResume(String),
+    #[error("{0} events were dropped because the subscriber fell behind")]
+    EventsDropped(u64),
+    #[error("Unknown error: {0}")]
+    Other(Box<dyn std::error::Error + Sync + Send>),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A Quick Share instance.
+///
+/// Clones are cheap handles to the same instance, and can be moved to other
+/// threads and tasks freely. The service stops once the last one is dropped.
+/// Internal locks are only held briefly and never across an `.await`, so
+/// calls don't block the runtime.
+#[derive(Clone)]
+pub struct Oxidrop {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    backend: Mutex<Box<dyn ShareBackend>>,
+    new_backend: backend::Factory,
+    sendinfo_send: Mutex<mpsc::Sender<SendInfo>>,
+    endpoint_send: Mutex<broadcast::WeakSender<EndpointInfo>>,
+    /// Recently announced endpoints with when they were last seen, replayed to
+    /// new discovery subscribers.
+    endpoints: Mutex<IndexMap<String, (Endpoint, Instant)>>,
+    /// Messages from every backend this instance has run, so subscribers survive
+    /// service restarts.
+    message_send: broadcast::Sender<ChannelMessage>,
+    event_send: broadcast::Sender<OxidropEvent>,
+    channel_capacity: usize,
+    staging: staging::Staging,
+    config: Mutex<Config>,
+    /// Whether the port was picked here rather than configured, so another
+    /// may be picked if it's taken by the time the service binds it.
+    pick_port: bool,
+    visibility: Mutex<Visibility>,
+    /// Held while starting the service, so concurrent first uses start it once.
+    start_lock: tokio::sync::Mutex<Option<oneshot::Receiver<()>>>,
+    status: Mutex<ServiceStatus>,
+    transfers: Mutex<HashMap<String, TransferState>>,
+    /// Who transfers are with and how far they got, alongside `transfers`.
+    details: Mutex<HashMap<String, TransferDetails>>,
+    metrics: Mutex<metrics::Recorder>,
+    /// Inbound requests still waiting for an answer, replayed to new
+    /// subscribers.
+    pending: Mutex<IndexMap<String, TransferRequest>>,
+    /// Feeds the streams from [`Oxidrop::get_transfer_requests`], only while
+    /// there are any. Locked after `pending`.
+    request_send: Mutex<Option<broadcast::Sender<TransferRequest>>>,
+    /// Where the files of inbound transfers go, recorded when they're offered.
+    incoming: Mutex<HashMap<String, incoming::IncomingTransfer>>,
+    /// What outbound transfers send and how far they got, kept after they end
+    /// so failed ones can be resumed.
+    outgoing: Mutex<HashMap<String, OutgoingTransfer>>,
+    send_queue: Mutex<queue::SendQueue>,
+    /// Numbers outbound transfers, so each has an id of its own.
+    next_send: AtomicU64,
+    /// Codes shown for outbound transfers, known once the devices have
+    /// finished their handshake.
+    pin_codes: Mutex<HashMap<String, String>>,
+    /// Ids of transfers that ended, oldest first, so the maps above don't
+    /// grow forever. Locked before them.
+    ended: Mutex<VecDeque<String>>,
+    accept_policy: Mutex<Option<AcceptPolicy>>,
+    trust_store: Option<TrustStore>,
+    #[cfg(feature = "history")]
+    history: Option<history::History>,
+    last_error: Mutex<Option<String>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+struct TransferDetails {
+    direction: TransferDirection,
+    peer: String,
+    progress: Option<Progress>,
+    rejection: Option<RejectReason>,
+    /// When the transfer last changed state or moved bytes.
+    active: Instant,
+    /// Whether it's being ended for going quiet too long.
+    timed_out: bool,
+}
+
+/// The files of an outbound transfer and how many of their bytes were sent.
+struct OutgoingTransfer {
+    endpoint: Endpoint,
+    /// Paths as handed to `rqs_lib`, in the order they're sent.
+    files: Vec<PathBuf>,
+    sizes: Vec<u64>,
+    transferred: u64,
+    /// Whether it was already sent again to a new address.
+    retried: bool,
+}
+
+impl OutgoingTransfer {
+    /// The files that weren't completely sent yet.
+    fn unsent_files(&self) -> Vec<PathBuf> {
+        let mut end = 0;
+        self.files
+            .iter()
+            .zip(&self.sizes)
+            .filter(|(_, size)| {
+                end += *size;
+                end > self.transferred
+            })
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const REDISCOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_CHANNEL_CAPACITY: usize = 10;
+const ENDPOINT_TTL: Duration = Duration::from_secs(60);
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESTART_ATTEMPTS: u32 = 6;
+/// How many other ports to try when a picked one is taken before it's bound.
+const MAX_PORT_ATTEMPTS: u32 = 3;
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+const TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How many ended transfers are remembered, for looking up how they ended,
+/// resuming them or listing their files. Older ones are forgotten.
+const MAX_ENDED_TRANSFERS: usize = 256;
+
+impl Hash for TransferRequest {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.id.hash(state);
+    }
+}
+
+impl PartialEq for TransferRequest {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id == other.0.id
+    }
+}
+
+impl Eq for TransferRequest {}
+
+impl Hash for Endpoint {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.id.hash(state);
+    }
+}
+
+impl PartialEq for Endpoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.id == other.0.id
+    }
+}
+
+impl Eq for Endpoint {}
+
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .field("device_type", &self.device_type())
+            .field("ip", &self.ip())
+            .field("port", &self.port())
+            .finish()
+    }
+}
+
+impl Endpoint {
+    /// An endpoint at a known address, for sending on networks where
+    /// discovery doesn't work. Quick Share doesn't check names, so `name` is
+    /// only used for display.
+    pub fn from_addr(addr: SocketAddr, name: &str) -> Self {
+        Endpoint(EndpointInfo {
+            fullname: String::new(),
+            id: addr.to_string(),
+            name: Some(name.to_string()),
+            ip: Some(match addr {
+                // Link-local addresses only work with their interface.
+                SocketAddr::V6(v6) if v6.scope_id() != 0 => {
+                    format!("{}%{}", v6.ip(), v6.scope_id())
+                }
+                _ => addr.ip().to_string(),
+            }),
+            port: Some(addr.port().to_string()),
+            rtype: None,
+            present: None,
+        })
+    }
+
+    /// Identifies the device for as long as it keeps advertising. Devices
+    /// pick a new id when they restart advertising, so it isn't permanent.
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub fn name(&self) -> &str {
+        self.0.name.as_ref().unwrap_or(&self.0.fullname)
+    }
+
+    /// The mDNS service name the device advertises itself under, empty for
+    /// endpoints made by [`from_addr`](Self::from_addr).
+    pub fn fullname(&self) -> &str {
+        &self.0.fullname
+    }
+
+    pub fn device_type(&self) -> DeviceType {
+        match self.0.rtype {
+            Some(rqs_lib::DeviceType::Phone) => DeviceType::Phone,
+            Some(rqs_lib::DeviceType::Tablet) => DeviceType::Tablet,
+            Some(rqs_lib::DeviceType::Laptop) => DeviceType::Laptop,
+            Some(rqs_lib::DeviceType::Unknown) | None => DeviceType::Unknown,
+        }
+    }
+
+    /// The address without the interface of an IPv6 link-local one.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.0.ip.as_ref()?.split('%').next()?.parse().ok()
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.0.port.as_ref()?.parse().ok()
+    }
+
+    /// Where to connect to, with IPv6 addresses in brackets so the port can be
+    /// told apart.
+    fn addr(&self) -> String {
+        let ip = self.0.ip.as_ref().unwrap();
+        let port = self.0.port.as_ref().unwrap();
+        if ip.contains(':') {
+            format!("[{ip}]:{port}")
+        } else {
+            format!("{ip}:{port}")
+        }
+    }
+
+    fn is_discovered(&self) -> bool {
+        !self.0.fullname.is_empty()
+    }
+
+    /// Whether `other`, an announcement of the same endpoint, changes its
+    /// name or address.
+    fn changed_in(&self, other: &Endpoint) -> bool {
+        self.0.name != other.0.name || self.0.ip != other.0.ip || self.0.port != other.0.port
+    }
+}
+
+impl File {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        File {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// The name the receiving device sees.
+    pub fn wire_name(&self) -> Option<String> {
+        self.display_name.clone().or_else(|| {
+            self.path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+        })
+    }
+
+    /// `path` relative to `relative_to`, if set and `path` is inside it.
+    pub fn relative_path(&self) -> Option<&Path> {
+        self.path.strip_prefix(self.relative_to.as_ref()?).ok()
+    }
+}
+
+impl TransferHandle {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl TransferRequest {
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub fn sender_name(&self) -> &str {
+        self.0
+            .meta
+            .as_ref()
+            .and_then(|m| m.source.as_ref())
+            .map(|s| &s.name)
+            .unwrap_or(&self.0.id)
+    }
+
+    /// Names of the files offered, empty if the request only carries text.
+    pub fn files(&self) -> &[String] {
+        self.0
+            .meta
+            .as_ref()
+            .and_then(|m| m.files.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Text offered along with or instead of files, such as a note or a URL.
+    pub fn text(&self) -> Option<&str> {
+        self.0.meta.as_ref()?.text_description.as_deref()
+    }
+
+    /// Size of everything offered, in bytes. Quick Share only announces the
+    /// total up front, so sizes of individual files aren't known until they
+    /// arrive, and neither are their types.
+    pub fn total_bytes(&self) -> u64 {
+        self.0.meta.as_ref().map_or(0, |m| m.total_bytes)
+    }
+
+    /// What's offered. Requests carrying text are delivered through
+    /// [`OxidropEvent::TextReceived`] once accepted, not written to disk.
+    pub fn payload(&self) -> Payload {
+        self.0
+            .meta
+            .as_ref()
+            .map_or(Payload::Files(vec![]), Payload::from_metadata)
+    }
+
+    /// The code both devices show for this transfer. Comparing them confirms
+    /// the request really comes from the device it claims to.
+    pub fn pin_code(&self) -> Option<&str> {
+        self.0.meta.as_ref()?.pin_code.as_deref()
+    }
+
+    /// Number of items offered, counting text as one.
+    pub fn item_count(&self) -> usize {
+        self.files().len() + usize::from(self.text().is_some())
+    }
+}
+
+/// The first file `request` offers whose name `policy` doesn't allow.
+fn unsafe_file_name(request: &TransferRequest, policy: FileNamePolicy) -> Option<&str> {
+    let allowed = |name: &str| match policy {
+        FileNamePolicy::Keep => true,
+        FileNamePolicy::Sanitize => !sanitize::escapes_dir(name),
+        FileNamePolicy::Reject => {
+            !sanitize::escapes_dir(name) && sanitize::sanitize(name).is_none()
+        }
+    };
+    request
+        .files()
+        .iter()
+        .map(String::as_str)
+        .find(|name| !allowed(name))
+}
+
+impl Inner {
+    /// Starts the service unless it's already running. Sockets are only opened
+    /// once something needs them, so merely constructing [`Oxidrop`] is cheap.
+    async fn ensure_started(self: &Arc<Self>) -> Result<()> {
+        let mut shutdown_recv = self.start_lock.lock().await;
+        let Some(shutdown) = shutdown_recv.take() else {
+            return Ok(());
+        };
+
+        match start_backend(self).await {
+            Ok((backend, sendinfo_send)) => {
+                *self.backend.lock().map_err(|_| Error::CorruptedState)? = backend;
+                *self
+                    .sendinfo_send
+                    .lock()
+                    .map_err(|_| Error::CorruptedState)? = sendinfo_send;
+                self.set_status(ServiceStatus::Running);
+                tokio::spawn(supervise(Arc::downgrade(self), shutdown));
+                Ok(())
+            }
+            Err(e) => {
+                self.record_error(&e);
+                *shutdown_recv = Some(shutdown);
+                Err(e)
+            }
+        }
+    }
+
+    /// Replaces the dead backend with a freshly started one, resuming
+    /// discovery if anyone is still listening for endpoints.
+    async fn restart(&self) -> Result<()> {
+        let placeholder = (self.new_backend)(&self.config(), rqs_lib::Visibility::Invisible);
+        let mut old = std::mem::replace(
+            &mut *self.backend.lock().map_err(|_| Error::CorruptedState)?,
+            placeholder,
+        );
+        old.stop().await;
+
+        let (mut backend, sendinfo_send) = start_backend(self).await?;
+        if let Some(endpoint_send) = self
+            .endpoint_send
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .upgrade()
+        {
+            backend.discover(endpoint_send)?;
+        }
+
+        *self.backend.lock().map_err(|_| Error::CorruptedState)? = backend;
+        *self
+            .sendinfo_send
+            .lock()
+            .map_err(|_| Error::CorruptedState)? = sendinfo_send;
+        Ok(())
+    }
+
+    fn send_action(&self, request: &TransferRequest, action: ChannelAction) -> Result<()> {
+        self.send_action_to(request.id(), action)
+    }
+
+    /// Rejects an inbound request and records why. Leaves `pending` alone, so
+    /// it can be called while that's locked.
+    fn reject(&self, request: &TransferRequest, reason: RejectReason) -> Result<()> {
+        self.send_action(request, ChannelAction::RejectTransfer)?;
+        if let Some(details) = self
+            .details
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get_mut(request.id())
+        {
+            details.rejection = Some(reason);
+        }
+        let _ = self.event_send.send(OxidropEvent::TransferRejected {
+            id: request.id().to_string(),
+            reason,
+        });
+        Ok(())
+    }
+
+    /// Accepts an inbound request and records it as accepted, since `rqs_lib`
+    /// says nothing until the first bytes arrive.
+    fn accept(&self, request: &TransferRequest) -> Result<()> {
+        let prepared = self
+            .incoming
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get_mut(request.id())
+            .map_or(Ok(()), |t| t.prepare(self.config().overwrite_policy));
+        if let Err(path) = prepared {
+            // Otherwise the sender would wait for an answer that never comes.
+            self.reject(request, RejectReason::Declined)?;
+            return Err(Error::FileExists(path));
+        }
+        self.send_action(request, ChannelAction::AcceptTransfer)?;
+        self.pending
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .shift_remove(request.id());
+
+        let mut transfers = self.transfers.lock().map_err(|_| Error::CorruptedState)?;
+        if transfers.get(request.id()) == Some(&TransferState::WaitingForConsent) {
+            transfers.insert(request.id().to_string(), TransferState::Accepted);
+            drop(transfers);
+            let _ = self.event_send.send(OxidropEvent::TransferStateChanged {
+                id: request.id().to_string(),
+                direction: TransferDirection::Inbound,
+                state: TransferState::Accepted,
+            });
+        }
+        Ok(())
+    }
+
+    fn decide(&self, request: &TransferRequest) -> Decision {
+        let policy = self
+            .accept_policy
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        policy.map_or(Decision::Ask, |policy| policy(request))
+    }
+
+    fn send_action_to(&self, id: &str, action: ChannelAction) -> Result<()> {
+        self.backend
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .act(id, action)
+    }
+
+    /// Asks transfer `id` to cancel and waits for it to end. If the service
+    /// doesn't confirm in time, the transfer is considered cancelled anyway
+    /// and announced as such, so callers aren't left waiting.
+    async fn cancel(&self, id: &str) -> Result<TransferState> {
+        if self.cancel_queued(id)? {
+            return Ok(TransferState::Cancelled);
+        }
+
+        let mut event_recv = self.event_send.subscribe();
+        let current = self
+            .transfers
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get(id)
+            .copied();
+        if let Some(state) = current.filter(|s| s.is_final()) {
+            return Ok(state);
+        }
+        self.send_action_to(id, ChannelAction::CancelTransfer)?;
+
+        let ended = timeout(CANCEL_TIMEOUT, async {
+            loop {
+                match event_recv.recv().await {
+                    Ok(OxidropEvent::TransferStateChanged {
+                        id: event_id,
+                        state,
+                        ..
+                    }) if event_id == id && state.is_final() => return Some(state),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .await
+        .ok()
+        .flatten();
+        if let Some(state) = ended {
+            return Ok(state);
+        }
+
+        log::warn!("Transfer {id} didn't confirm cancellation, considering it cancelled");
+        let state = if self.timed_out(id) {
+            TransferState::TimedOut
+        } else {
+            TransferState::Cancelled
+        };
+        let mut transfers = self.transfers.lock().map_err(|_| Error::CorruptedState)?;
+        if let Some(state) = transfers.get(id).copied().filter(|s| s.is_final()) {
+            return Ok(state);
+        }
+        transfers.insert(id.to_string(), state);
+        drop(transfers);
+        self.metrics
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .ended(state);
+        self.retire(id);
+        let direction = if self
+            .outgoing
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .contains_key(id)
+        {
+            TransferDirection::Outbound
+        } else {
+            TransferDirection::Inbound
+        };
+        let _ = self.event_send.send(OxidropEvent::TransferStateChanged {
+            id: id.to_string(),
+            direction,
+            state,
+        });
+        Ok(state)
+    }
+
+    /// Records that transfer `id` ended, forgetting everything about the
+    /// oldest ended transfers beyond [`MAX_ENDED_TRANSFERS`].
+    fn retire(&self, id: &str) {
+        let mut ended = self.ended.lock().unwrap_or_else(|e| e.into_inner());
+        if ended.iter().any(|e| e == id) {
+            return;
+        }
+        ended.push_back(id.to_string());
+        while ended.len() > MAX_ENDED_TRANSFERS {
+            let Some(old) = ended.pop_front() else {
+                break;
+            };
+            self.transfers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&old);
+            self.details
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&old);
+            self.incoming
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&old);
+            self.outgoing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&old);
+            self.pin_codes
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&old);
+        }
+    }
+
+    /// Whether transfer `id` is being ended by [`watch_timeouts`].
+    fn timed_out(&self, id: &str) -> bool {
+        self.details
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .is_some_and(|d| d.timed_out)
+    }
+
+    /// Takes transfer `id` out of the send queue, if it's waiting there, and
+    /// announces it as cancelled. Returns whether it was waiting.
+    fn cancel_queued(&self, id: &str) -> Result<bool> {
+        let queued = self
+            .send_queue
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .remove(id);
+        if queued.is_none() {
+            return Ok(false);
+        }
+        // Queued sends have ids of their own, so this can't end a transfer
+        // that's already running.
+        self.transfers
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .entry(id.to_string())
+            .or_insert(TransferState::Cancelled);
+        self.metrics
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .ended(TransferState::Cancelled);
+        self.retire(id);
+        let _ = self.event_send.send(OxidropEvent::TransferStateChanged {
+            id: id.to_string(),
+            direction: TransferDirection::Outbound,
+            state: TransferState::Cancelled,
+        });
+        Ok(true)
+    }
+
+    /// Ids of sends waiting in the queue, followed by those of transfers that
+    /// have started and not reached a final state. Inbound requests still
+    /// waiting for consent don't count.
+    fn active_transfers(&self) -> Vec<String> {
+        let mut active: Vec<_> = self
+            .send_queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .waiting()
+            .map(|w| w.info.id.clone())
+            .collect();
+        let pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        active.extend(
+            self.transfers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .filter(|(id, state)| !state.is_final() && !pending.contains_key(*id))
+                .map(|(id, _)| id.clone()),
+        );
+        active
+    }
+
+    /// Which file of transfer `id` is being transferred once `transferred`
+    /// bytes are done, if that can be told.
+    fn file_index(
+        &self,
+        id: &str,
+        direction: TransferDirection,
+        transferred: u64,
+    ) -> Option<usize> {
+        match direction {
+            TransferDirection::Outbound => {
+                let outgoing = self.outgoing.lock().unwrap_or_else(|e| e.into_inner());
+                let sizes = &outgoing.get(id)?.sizes;
+                let mut end = 0;
+                let index = sizes.iter().position(|size| {
+                    end += size;
+                    end > transferred
+                });
+                index.or(sizes.len().checked_sub(1))
+            }
+            TransferDirection::Inbound => {
+                let incoming = self.incoming.lock().unwrap_or_else(|e| e.into_inner());
+                incoming.get(id)?.files(false).len().checked_sub(1)
+            }
+        }
+    }
+
+    /// Name of the device on the other side of transfer `msg.id`.
+    fn peer_name(&self, msg: &ChannelMessage, direction: TransferDirection) -> String {
+        match direction {
+            TransferDirection::Outbound => self
+                .outgoing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&msg.id)
+                .map_or_else(|| msg.id.clone(), |t| t.endpoint.name().to_string()),
+            TransferDirection::Inbound => TransferRequest(msg.clone()).sender_name().to_string(),
+        }
+    }
+
+    /// Where the files of transfer `id` are on this side.
+    fn transfer_paths(&self, id: &str, direction: TransferDirection) -> Vec<PathBuf> {
+        match direction {
+            TransferDirection::Outbound => self
+                .outgoing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(id)
+                .map(|t| t.files.clone())
+                .unwrap_or_default(),
+            TransferDirection::Inbound => self
+                .incoming
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(id)
+                .map(|t| t.files(true).into_iter().map(|f| f.path).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The free space in the download directory if `request` doesn't fit
+    /// in it, announcing that with [`OxidropEvent::InsufficientSpace`]
+    /// unless the space policy is to ignore it.
+    fn space_for(&self, request: &TransferRequest) -> Option<u64> {
+        if self.config().space_policy == SpacePolicy::Ignore {
+            return None;
+        }
+        let available = space::available(&self.download_dir()?)?;
+        if request.total_bytes() <= available {
+            return None;
+        }
+        let _ = self.event_send.send(OxidropEvent::InsufficientSpace {
+            request: request.clone(),
+            available,
+        });
+        Some(available)
+    }
+
+    /// The directory received files are saved in, which is the user's
+    /// downloads directory unless configured otherwise.
+    fn download_dir(&self) -> Option<PathBuf> {
+        self.config().download_dir.or_else(|| {
+            directories::UserDirs::new().and_then(|d| d.download_dir().map(Path::to_path_buf))
+        })
+    }
+
+    fn config(&self) -> Config {
+        self.config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn visibility(&self) -> rqs_lib::Visibility {
+        match *self.visibility.lock().unwrap_or_else(|e| e.into_inner()) {
+            Visibility::Visible => rqs_lib::Visibility::Visible,
+            Visibility::Invisible => rqs_lib::Visibility::Invisible,
+            Visibility::Temporarily => rqs_lib::Visibility::Temporarily,
+        }
+    }
+
+    /// Caches an announced endpoint, announcing it on the event bus the first
+    /// time it's seen and when it changes. Returns whether it wasn't cached.
+    fn remember_endpoint(&self, endpoint: &Endpoint) -> bool {
+        let previous = self
+            .endpoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(endpoint.0.id.clone(), (endpoint.clone(), Instant::now()));
+        let event = match &previous {
+            None => OxidropEvent::EndpointDiscovered(endpoint.clone()),
+            Some((previous, _)) if previous.changed_in(endpoint) => {
+                OxidropEvent::EndpointUpdated(endpoint.clone())
+            }
+            Some(_) => return false,
+        };
+        let _ = self.event_send.send(event);
+        previous.is_none()
+    }
+
+    /// Drops a lost endpoint from the cache, announcing the loss the first
+    /// time any discovery stream notices it.
+    fn forget_endpoint(&self, id: &str) {
+        let removed = self
+            .endpoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .shift_remove(id);
+        if let Some((endpoint, _)) = removed {
+            let _ = self.event_send.send(OxidropEvent::EndpointLost(endpoint));
+        }
+    }
+
+    fn cached_endpoints(&self) -> Result<Vec<Endpoint>> {
+        self.expire_endpoints();
+        let endpoints = self.endpoints.lock().map_err(|_| Error::CorruptedState)?;
+        Ok(endpoints.values().map(|(e, _)| e.clone()).collect())
+    }
+
+    /// Forgets endpoints that haven't announced themselves for
+    /// [`ENDPOINT_TTL`], since devices that drop off the network don't always
+    /// say goodbye.
+    fn expire_endpoints(&self) {
+        let expired: Vec<_> = self
+            .endpoints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|(_, seen)| seen.elapsed() >= ENDPOINT_TTL)
+            .map(|(e, _)| e.0.id.clone())
+            .collect();
+        for id in expired {
+            self.forget_endpoint(&id);
+        }
+    }
+
+    fn set_status(&self, status: ServiceStatus) {
+        *self.status.lock().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    fn record_error(&self, error: impl ToString) {
+        *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(error.to_string());
+    }
+
+    /// Sends outbound transfer `msg.id` again if it failed while connecting and
+    /// its endpoint turns up at a new address, returning whether it's being
+    /// retried. If it doesn't, `msg` is reported again to end the transfer.
+    fn retry_send(self: &Arc<Self>, msg: &ChannelMessage) -> bool {
+        let connecting = self
+            .transfers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&msg.id)
+            .is_none_or(|s| *s == TransferState::Initiated);
+        let retry = self
+            .outgoing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(&msg.id)
+            .filter(|t| connecting && !t.retried && t.endpoint.is_discovered())
+            .map(|t| {
+                t.retried = true;
+                (t.endpoint.clone(), t.files.clone())
+            });
+        let Some((endpoint, files)) = retry else {
+            return false;
+        };
+
+        log::info!(
+            "Connecting to {} failed, looking for it at a new address",
+            endpoint.name()
+        );
+        let inner = self.clone();
+        let msg = msg.clone();
+        tokio::spawn(async move {
+            let oxidrop = Oxidrop {
+                inner: inner.clone(),
+            };
+            let resent = match oxidrop.resolve_again(&endpoint).await {
+                Ok(current) => {
+                    let _ = inner.event_send.send(OxidropEvent::EndpointAddressChanged {
+                        previous: endpoint,
+                        current: current.clone(),
+                    });
+                    if let Some(t) = inner
+                        .outgoing
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .get_mut(&msg.id)
+                    {
+                        t.endpoint = current.clone();
+                    }
+                    let info = SendInfo {
+                        id: msg.id.clone(),
+                        name: current.name().to_string(),
+                        addr: current.addr(),
+                        ob: OutboundPayload::Files(
+                            files
+                                .iter()
+                                .map(|f| f.to_string_lossy().into_owned())
+                                .collect(),
+                        ),
+                    };
+                    inner.dispatch(info).await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = resent {
+                log::warn!("Failed to send transfer {} again: {e}", msg.id);
+                let _ = inner.message_send.send(msg);
+            }
+        });
+        true
+    }
+
+    /// Frees the send slot of transfer `id` and starts the next queued send
+    /// in its place.
+    fn release_slot(self: &Arc<Self>, id: &str) {
+        let limit = self.config().max_concurrent_sends;
+        let next = self
+            .send_queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .finish(id, limit);
+        self.start_queued(next);
+    }
+
+    /// Starts queued sends while there are free slots, e.g. after the limit
+    /// was raised.
+    fn start_ready(self: &Arc<Self>) {
+        let limit = self.config().max_concurrent_sends;
+        let ready = self
+            .send_queue
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .start_ready(limit);
+        self.start_queued(ready);
+    }
+
+    /// Hands sends that were given a slot to the backend. One that can't be
+    /// handed over ends as [`TransferState::Failed`] and frees its slot in
+    /// turn.
+    fn start_queued(self: &Arc<Self>, sends: Vec<SendInfo>) {
+        for next in sends {
+            let inner = self.clone();
+            tokio::spawn(async move {
+                let id = next.id.clone();
+                if let Err(e) = inner.dispatch(next).await {
+                    log::warn!("Failed to start queued transfer: {e}");
+                    inner
+                        .transfers
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(id.clone(), TransferState::Failed);
+                    inner.retire(&id);
+                    let _ = inner.event_send.send(OxidropEvent::TransferStateChanged {
+                        id: id.clone(),
+                        direction: TransferDirection::Outbound,
+                        state: TransferState::Failed,
+                    });
+                    let _ = inner.event_send.send(OxidropEvent::TransferFailed {
+                        id: id.clone(),
+                        direction: TransferDirection::Outbound,
+                    });
+                    inner.release_slot(&id);
+                }
+            });
+        }
+    }
+
+    /// Hands an outbound transfer to `rqs_lib`.
+    async fn dispatch(&self, info: SendInfo) -> Result<()> {
+        #[cfg(feature = "proto-debug")]
+        proto_debug::log_send(&info);
+        self.sendinfo_send()?
+            .send(info)
+            .await
+            .map_err(|e| Error::Other(Box::new(e)))
+    }
+
+    fn sendinfo_send(&self) -> Result<mpsc::Sender<SendInfo>> {
+        Ok(self
+            .sendinfo_send
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .clone())
+    }
+}
+
+async fn start_backend(inner: &Inner) -> Result<(Box<dyn ShareBackend>, mpsc::Sender<SendInfo>)> {
+    let mut attempts = 0;
+    let (backend, sendinfo_send) = loop {
+        let mut backend = (inner.new_backend)(&inner.config(), inner.visibility());
+        match backend.start().await {
+            Ok(sendinfo_send) => break (backend, sendinfo_send),
+            Err(e) if inner.pick_port && attempts < MAX_PORT_ATTEMPTS && is_addr_in_use(&e) => {
+                let port = free_port()?;
+                log::info!("Picked port was taken before it was bound, trying {port}");
+                inner.config.lock().map_err(|_| Error::CorruptedState)?.port = Some(port);
+                attempts += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    let mut message_recv = backend.messages();
+    let message_send = inner.message_send.clone();
+    tokio::spawn(async move {
+        loop {
+            match message_recv.recv().await {
+                Ok(msg) => {
+                    #[cfg(feature = "proto-debug")]
+                    proto_debug::log_message(&msg);
+                    let _ = message_send.send(msg);
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Missed {n} messages from rqs_lib");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+
+    Ok((backend, sendinfo_send))
+}
+
+/// Watches the service's send loop, which owns the receiving end of
+/// `sendinfo_send`, and restarts the service with exponential backoff once it
+/// exits.
+async fn supervise(inner: Weak<Inner>, mut shutdown: oneshot::Receiver<()>) {
+    loop {
+        let Some(sendinfo_send) = inner.upgrade().and_then(|i| i.sendinfo_send().ok()) else {
+            return;
+        };
+        tokio::select! {
+            _ = sendinfo_send.closed() => {}
+            _ = &mut shutdown => return,
+        }
+        drop(sendinfo_send);
+        log::warn!("Service exited unexpectedly, restarting");
+        if let Some(inner) = inner.upgrade() {
+            inner.set_status(ServiceStatus::Restarting);
+            inner.record_error("Service exited unexpectedly");
+        }
+
+        let mut backoff = RESTART_BACKOFF;
+        for attempts in 1..=MAX_RESTART_ATTEMPTS {
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            match inner.restart().await {
+                Ok(()) => {
+                    inner.set_status(ServiceStatus::Running);
+                    let _ = inner
+                        .event_send
+                        .send(OxidropEvent::ServiceRestarted { attempts });
+                    break;
+                }
+                Err(e) if attempts == MAX_RESTART_ATTEMPTS => {
+                    log::error!("Giving up restarting service: {e}");
+                    inner.set_status(ServiceStatus::Failed);
+                    inner.record_error(&e);
+                    let _ = inner
+                        .event_send
+                        .send(OxidropEvent::ServiceFailed(e.to_string()));
+                    return;
+                }
+                Err(e) => {
+                    log::warn!("Failed to restart service: {e}");
+                    inner.record_error(e);
+                }
+            }
+            sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
+
+/// Ends transfers that wait for consent or stall longer than the config
+/// allows. The state `rqs_lib` reports for them afterwards is announced as
+/// [`TransferState::TimedOut`] instead.
+async fn watch_timeouts(inner: Weak<Inner>) {
+    let mut interval = tokio::time::interval(TIMEOUT_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        let config = inner.config();
+        if config.consent_timeout.is_none() && config.stall_timeout.is_none() {
+            continue;
+        }
+
+        let states: Vec<_> = inner
+            .transfers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(_, state)| !state.is_final())
+            .map(|(id, state)| (id.clone(), *state))
+            .collect();
+        let mut expired = Vec::new();
+        {
+            let mut details = inner.details.lock().unwrap_or_else(|e| e.into_inner());
+            for (id, state) in states {
+                let limit = match state {
+                    TransferState::WaitingForConsent => config.consent_timeout,
+                    TransferState::Accepted | TransferState::Transferring => config.stall_timeout,
+                    _ => None,
+                };
+                if let Some(limit) = limit
+                    && let Some(d) = details.get_mut(&id)
+                    && !d.timed_out
+                    && d.active.elapsed() >= limit
+                {
+                    d.timed_out = true;
+                    expired.push((id, d.direction, state));
+                }
+            }
+        }
+
+        for (id, direction, state) in expired {
+            log::info!("Transfer {id} timed out while {state:?}");
+            if direction == TransferDirection::Inbound && state == TransferState::WaitingForConsent
+            {
+                if let Err(e) = inner.send_action_to(&id, ChannelAction::RejectTransfer) {
+                    log::warn!("Failed to reject transfer {id}: {e}");
+                }
+            } else {
+                let inner = inner.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = inner.cancel(&id).await {
+                        log::warn!("Failed to cancel transfer {id}: {e}");
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Follows the state of every transfer on the message bus and announces
+/// each change as an [`OxidropEvent::TransferStateChanged`].
+async fn track_transfers(
+    inner: Weak<Inner>,
+    mut message_recv: broadcast::Receiver<ChannelMessage>,
+) {
+    loop {
+        let msg = match message_recv.recv().await {
+            Ok(msg) => msg,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                log::warn!("Transfer tracking missed {n} messages");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let Some((direction, state)) = TransferState::from_message(&msg) else {
+            continue;
+        };
+        let Some(inner) = inner.upgrade() else {
+            return;
+        };
+        if direction == TransferDirection::Outbound
+            && state == TransferState::Failed
+            && inner.retry_send(&msg)
+        {
+            continue;
+        }
+        let state = if matches!(
+            state,
+            TransferState::Rejected | TransferState::Cancelled | TransferState::Failed
+        ) && inner.timed_out(&msg.id)
+        {
+            TransferState::TimedOut
+        } else {
+            state
+        };
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("transfer", id = %msg.id, ?direction).entered();
+
+        let mut transfers = inner.transfers.lock().unwrap_or_else(|e| e.into_inner());
+        let prev = transfers.get(&msg.id).copied();
+        if prev.is_some_and(TransferState::is_final) {
+            continue;
+        }
+        transfers.insert(msg.id.clone(), state);
+        drop(transfers);
+        if state.is_final() {
+            inner
+                .metrics
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .ended(state);
+            inner.retire(&msg.id);
+        }
+        if prev.is_none() {
+            let peer = inner.peer_name(&msg, direction);
+            inner
+                .details
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(
+                    msg.id.clone(),
+                    TransferDetails {
+                        direction,
+                        peer,
+                        progress: None,
+                        rejection: None,
+                        active: Instant::now(),
+                        timed_out: false,
+                    },
+                );
+        }
+
+        if direction == TransferDirection::Outbound
+            && let Some(pin_code) = msg.meta.as_ref().and_then(|m| m.pin_code.clone())
+        {
+            inner
+                .pin_codes
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(msg.id.clone(), pin_code);
+        }
+
+        if let Some(mut progress) = Progress::from_message(&msg) {
+            if direction == TransferDirection::Outbound
+                && let Some(outgoing) = inner
+                    .outgoing
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get_mut(&msg.id)
+            {
+                outgoing.transferred = progress.transferred;
+            }
+            if direction == TransferDirection::Inbound
+                && let Some(incoming) = inner
+                    .incoming
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get_mut(&msg.id)
+            {
+                incoming.received = progress.transferred;
+            }
+            progress.file_index = inner.file_index(&msg.id, direction, progress.transferred);
+            if let Some(details) = inner
+                .details
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_mut(&msg.id)
+            {
+                let moved = progress
+                    .transferred
+                    .saturating_sub(details.progress.map_or(0, |p| p.transferred));
+                if moved > 0 {
+                    details.active = Instant::now();
+                    inner
+                        .metrics
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .moved(direction, moved);
+                }
+                details.progress = Some(progress);
+            }
+            let _ = inner.event_send.send(OxidropEvent::TransferProgress {
+                id: msg.id.clone(),
+                direction,
+                progress,
+            });
+        }
+        if prev == Some(state) {
+            continue;
+        }
+        if let Some(details) = inner
+            .details
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(&msg.id)
+        {
+            details.active = Instant::now();
+        }
+
+        let request = (direction == TransferDirection::Inbound
+            && state == TransferState::WaitingForConsent)
+            .then(|| TransferRequest(msg.clone()));
+        let config = inner.config();
+        let short_of_space = request
+            .as_ref()
+            .filter(|_| !config.send_only)
+            .and_then(|r| inner.space_for(r));
+        let out_of_space = short_of_space.is_some() && config.space_policy == SpacePolicy::Reject;
+        // Decided before locking, so policies can call back into `Oxidrop`.
+        let decision = match &request {
+            Some(request)
+                if !config.send_only
+                    && unsafe_file_name(request, config.file_names).is_none()
+                    && !out_of_space
+                    && config.allows_sender(request.sender_name()) =>
+            {
+                inner.decide(request)
+            }
+            _ => Decision::Ask,
+        };
+        let mut accepted = None;
+        let mut pending = inner.pending.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(request) = request {
+            if config.send_only {
+                log::info!(
+                    "Rejecting transfer from {} in send-only mode",
+                    request.sender_name()
+                );
+                if let Err(e) = inner.reject(&request, RejectReason::Declined) {
+                    log::warn!("Failed to reject transfer: {e}");
+                }
+            } else if let Some(name) = unsafe_file_name(&request, config.file_names) {
+                log::warn!(
+                    "Rejecting transfer from {} offering a file named {name:?}",
+                    request.sender_name()
+                );
+                if let Err(e) = inner.reject(&request, RejectReason::Declined) {
+                    log::warn!("Failed to reject transfer: {e}");
+                }
+            } else if out_of_space {
+                log::info!(
+                    "Rejecting transfer from {} that doesn't fit in the download directory",
+                    request.sender_name()
+                );
+                if let Err(e) = inner.reject(&request, RejectReason::NotEnoughSpace) {
+                    log::warn!("Failed to reject transfer: {e}");
+                }
+            } else if !config.allows_sender(request.sender_name()) {
+                log::info!(
+                    "Rejecting transfer from blocked sender {}",
+                    request.sender_name()
+                );
+                if let Err(e) = inner.reject(&request, RejectReason::Declined) {
+                    log::warn!("Failed to reject transfer: {e}");
+                }
+                let _ = inner
+                    .event_send
+                    .send(OxidropEvent::TransferRequestBlocked(request));
+            } else {
+                if let Some(files) = msg.meta.as_ref().and_then(|m| m.files.clone())
+                    && let Some(dir) = inner.download_dir()
+                {
+                    inner
+                        .incoming
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(msg.id.clone(), incoming::IncomingTransfer::new(dir, files));
+                }
+                match decision {
+                    Decision::Ask => {
+                        pending.insert(msg.id.clone(), request.clone());
+                        if let Some(request_send) = inner
+                            .request_send
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .as_ref()
+                        {
+                            let _ = request_send.send(request.clone());
+                        }
+                        let _ = inner
+                            .event_send
+                            .send(OxidropEvent::TransferRequested(request));
+                    }
+                    Decision::Accept => {
+                        log::info!(
+                            "Accepting transfer from {} by policy",
+                            request.sender_name()
+                        );
+                        accepted = Some(request);
+                    }
+                    Decision::Reject => {
+                        log::info!(
+                            "Rejecting transfer from {} by policy",
+                            request.sender_name()
+                        );
+                        if let Err(e) = inner.reject(&request, RejectReason::Declined) {
+                            log::warn!("Failed to reject transfer: {e}");
+                        }
+                    }
+                }
+            }
+        } else if let Some(request) = pending.shift_remove(&msg.id)
+            && state.is_final()
+        {
+            let _ = inner
+                .event_send
+                .send(OxidropEvent::TransferRequestWithdrawn(request));
+        }
+        drop(pending);
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(?prev, ?state, "transfer state changed");
+        let _ = inner.event_send.send(OxidropEvent::TransferStateChanged {
+            id: msg.id.clone(),
+            direction,
+            state,
+        });
+        if direction == TransferDirection::Inbound && state.is_final() {
+            let policy = inner.config().overwrite_policy;
+            let mut incoming = inner.incoming.lock().unwrap_or_else(|e| e.into_inner());
+            let transfer = incoming.get_mut(&msg.id);
+            let done = state == TransferState::Done;
+            let (conflicts, renamed) = match transfer {
+                Some(t) => (
+                    t.finish(done, policy),
+                    if done && inner.config().file_names == FileNamePolicy::Sanitize {
+                        t.sanitize_names()
+                    } else {
+                        vec![]
+                    },
+                ),
+                None => (vec![], vec![]),
+            };
+            drop(incoming);
+            for (name, path) in renamed {
+                let _ = inner.event_send.send(OxidropEvent::FileRenamed {
+                    id: msg.id.clone(),
+                    name,
+                    path,
+                });
+            }
+            for conflict in conflicts {
+                let _ = inner.event_send.send(OxidropEvent::FileConflict {
+                    id: msg.id.clone(),
+                    path: conflict.path,
+                    policy,
+                    saved_as: conflict.saved_as,
+                });
+            }
+        }
+        // Accepted only now, so the acceptance follows the request's state
+        // change on the event bus.
+        if let Some(request) = accepted
+            && let Err(e) = inner.accept(&request)
+        {
+            log::warn!("Failed to accept transfer: {e}");
+        }
+        if direction == TransferDirection::Inbound
+            && state == TransferState::Done
+            && let Some(meta) = &msg.meta
+            && meta.text_description.is_some()
+        {
+            let _ = inner.event_send.send(OxidropEvent::TextReceived {
+                id: msg.id.clone(),
+                payload: Payload::from_metadata(meta),
+            });
+        }
+        let outcome = match state {
+            TransferState::Done => Some(OxidropEvent::TransferFinished {
+                id: msg.id.clone(),
+                direction,
+            }),
+            TransferState::Failed => Some(OxidropEvent::TransferFailed {
+                id: msg.id.clone(),
+                direction,
+            }),
+            _ => None,
+        };
+        if let Some(event) = outcome {
+            let _ = inner.event_send.send(event);
+        }
+        #[cfg(feature = "history")]
+        if let Some(history) = &inner.history {
+            if prev.is_none() {
+                history.start(&msg.id, inner.peer_name(&msg, direction));
+            }
+            if state.is_final() {
+                let files = inner
+                    .transfer_paths(&msg.id, direction)
+                    .into_iter()
+                    .map(|path| history::HistoryFile {
+                        size: std::fs::metadata(&path).map_or(0, |m| m.len()),
+                        path,
+                    })
+                    .collect();
+                let reason = inner
+                    .details
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .get(&msg.id)
+                    .and_then(|d| d.rejection);
+                if let Err(e) = history.finish(&msg.id, direction, state, files, reason) {
+                    log::warn!("Failed to record transfer in history: {e}");
+                }
+            }
+        }
+        if direction == TransferDirection::Outbound && state.is_final() {
+            inner.release_slot(&msg.id);
+        }
+        if state == TransferState::Done && inner.config().checksums {
+            let paths = inner.transfer_paths(&msg.id, direction);
+            let event_send = inner.event_send.clone();
+            tokio::spawn(async move {
+                let digests = checksum::digest_files(paths).await;
+                let _ = event_send.send(OxidropEvent::TransferChecksums {
+                    id: msg.id,
+                    direction,
+                    digests,
+                });
+            });
+        }
+    }
+}
+
+impl Oxidrop {
+    pub async fn new(config: Config) -> Result<Self> {
+        Self::with_backend(config, Arc::new(backend::new)).await
+    }
+
+    /// Like [`new`](Self::new), but running on backends made by `new_backend`.
+    pub(crate) async fn with_backend(
+        mut config: Config,
+        new_backend: backend::Factory,
+    ) -> Result<Self> {
+        let pick_port = config.port.is_none();
+        if pick_port {
+            // Picked here rather than by `rqs_lib`, so it can be told and
+            // stays the same across restarts of the service.
+            config.port = Some(free_port()?);
+        }
+        let (shutdown_send, shutdown_recv) = oneshot::channel();
+        let trust_store = config
+            .trust_store
+            .as_ref()
+            .map(TrustStore::open)
+            .transpose()?;
+        #[cfg(feature = "history")]
+        let history = config.history.clone().map(history::History::new);
+        let channel_capacity = config
+            .channel_capacity
+            .unwrap_or(DEFAULT_CHANNEL_CAPACITY)
+            .max(1);
+
+        let inner = Arc::new(Inner {
+            backend: Mutex::new(new_backend(&config, rqs_lib::Visibility::Invisible)),
+            new_backend,
+            sendinfo_send: Mutex::new(mpsc::channel(1).0),
+            channel_capacity,
+            endpoint_send: Mutex::new(broadcast::channel(1).0.downgrade()),
+            endpoints: Mutex::new(IndexMap::new()),
+            message_send: broadcast::channel(channel_capacity).0,
+            event_send: broadcast::channel(channel_capacity).0,
+            staging: staging::Staging::new(),
+            visibility: Mutex::new(config.visibility),
+            config: Mutex::new(config),
+            pick_port,
+            start_lock: tokio::sync::Mutex::new(Some(shutdown_recv)),
+            status: Mutex::new(ServiceStatus::Idle),
+            transfers: Mutex::new(HashMap::new()),
+            details: Mutex::new(HashMap::new()),
+            metrics: Mutex::new(metrics::Recorder::default()),
+            incoming: Mutex::new(HashMap::new()),
+            outgoing: Mutex::new(HashMap::new()),
+            send_queue: Mutex::new(queue::SendQueue::default()),
+            next_send: AtomicU64::new(0),
+            pin_codes: Mutex::new(HashMap::new()),
+            ended: Mutex::new(VecDeque::new()),
+            accept_policy: Mutex::new(None),
+            trust_store,
+            #[cfg(feature = "history")]
+            history,
+            pending: Mutex::new(IndexMap::new()),
+            request_send: Mutex::new(None),
+            last_error: Mutex::new(None),
+            _shutdown: shutdown_send,
+        });
+        tokio::spawn(track_transfers(
+            Arc::downgrade(&inner),
+            inner.message_send.subscribe(),
+        ));
+        tokio::spawn(watch_timeouts(Arc::downgrade(&inner)));
+
+        Ok(Oxidrop { inner })
+    }
+
+    /// Makes this device visible to nearby senders. Instances start out
+    /// hidden, so send-only clients never announce themselves.
+    pub async fn advertise(&self) -> Result<()> {
+        self.set_visibility(Visibility::Visible).await
+    }
+
+    pub async fn stop_advertising(&self) -> Result<()> {
+        self.set_visibility(Visibility::Invisible).await
+    }
+
+    /// Shows or hides this device, starting the service if it needs to be
+    /// seen. Emits [`OxidropEvent::VisibilityChanged`] if that changes
+    /// anything.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn set_visibility(&self, visibility: Visibility) -> Result<()> {
+        if visibility != Visibility::Invisible {
+            if self.inner.config().send_only {
+                return Err(Error::ReceivingDisabled);
+            }
+            self.inner.ensure_started().await?;
+        }
+
+        let mut backend = self
+            .inner
+            .backend
+            .lock()
+            .map_err(|_| Error::CorruptedState)?;
+        let previous = std::mem::replace(
+            &mut *self
+                .inner
+                .visibility
+                .lock()
+                .map_err(|_| Error::CorruptedState)?,
+            visibility,
+        );
+        backend.advertise(self.inner.visibility());
+        drop(backend);
+        if previous != visibility {
+            let _ = self
+                .inner
+                .event_send
+                .send(OxidropEvent::VisibilityChanged(visibility));
+        }
+        Ok(())
+    }
+
+    /// Changes where received files are saved, starting with the next
+    /// transfer.
+    pub async fn set_download_dir(&self, download_dir: Option<PathBuf>) -> Result<()> {
+        self.inner
+            .backend
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .set_download_dir(download_dir.clone());
+        self.inner
+            .config
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .download_dir = download_dir;
+        Ok(())
+    }
+
+    /// Applies `config` to the running instance, including its visibility,
+    /// accept rules and limits, and emits [`OxidropEvent::ConfigReloaded`].
+    /// Settings that can't change while the instance runs keep their current
+    /// values; their names are returned.
+    pub async fn reload_config(&self, config: Config) -> Result<Vec<String>> {
+        let current = self.inner.config();
+        let mut needs_restart = vec![];
+        if config.port.is_some_and(|port| Some(port) != current.port) {
+            needs_restart.push("port");
+        }
+        if config.send_only != current.send_only {
+            needs_restart.push("send_only");
+        }
+        if config.trust_store != current.trust_store {
+            needs_restart.push("trust_store");
+        }
+        #[cfg(feature = "history")]
+        if config.history != current.history {
+            needs_restart.push("history");
+        }
+        if config.channel_capacity != current.channel_capacity {
+            needs_restart.push("channel_capacity");
+        }
+
+        let visibility = config.visibility;
+        self.set_download_dir(config.download_dir.clone()).await?;
+        *self
+            .inner
+            .config
+            .lock()
+            .map_err(|_| Error::CorruptedState)? = Config {
+            port: current.port,
+            send_only: current.send_only,
+            trust_store: current.trust_store,
+            #[cfg(feature = "history")]
+            history: current.history,
+            channel_capacity: current.channel_capacity,
+            ..config
+        };
+        if !current.send_only {
+            self.set_visibility(visibility).await?;
+        }
+        self.inner.start_ready();
+
+        let needs_restart: Vec<_> = needs_restart.into_iter().map(String::from).collect();
+        let _ = self.inner.event_send.send(OxidropEvent::ConfigReloaded {
+            needs_restart: needs_restart.clone(),
+        });
+        Ok(needs_restart)
+    }
+
+    pub fn health(&self) -> Result<HealthReport> {
+        let status = *self
+            .inner
+            .status
+            .lock()
+            .map_err(|_| Error::CorruptedState)?;
+        let alive = !self.inner.sendinfo_send()?.is_closed();
+        let discovering = self
+            .inner
+            .endpoint_send
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .upgrade()
+            .is_some();
+
+        Ok(HealthReport {
+            status,
+            alive,
+            port: self.inner.config().port,
+            advertising: alive
+                && *self
+                    .inner
+                    .visibility
+                    .lock()
+                    .map_err(|_| Error::CorruptedState)?
+                    != Visibility::Invisible,
+            discovering: alive && discovering,
+            last_error: self
+                .inner
+                .last_error
+                .lock()
+                .map_err(|_| Error::CorruptedState)?
+                .clone(),
+        })
+    }
+
+    /// The port the service listens on, once it's running.
+    pub fn bound_port(&self) -> Option<u16> {
+        let running = *self.inner.status.lock().ok()? == ServiceStatus::Running;
+        running.then(|| self.inner.config().port).flatten()
+    }
+
+    /// The name other devices see. `rqs_lib` always advertises the
+    /// hostname, so that's what this reads; it can't be changed from here.
+    pub fn device_name(&self) -> String {
+        hostname::get()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "Unknown".to_string())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = request.id())))]
+    /// Accepts `request` and remembers its sender's name in the
+    /// [`TrustStore`], if there is one. Requests accepted by an
+    /// [accept policy](Self::set_accept_policy) aren't remembered.
+    pub async fn accept_transfer(&self, request: &TransferRequest) -> Result<()> {
+        self.inner.accept(request)?;
+        if let Some(trust_store) = &self.inner.trust_store
+            && let Err(e) = trust_store.add(request.sender_name())
+        {
+            log::warn!("Failed to remember the name {}: {e}", request.sender_name());
+        }
+        Ok(())
+    }
+
+    /// Bytes free in the download directory, if that can be told. Compare
+    /// with [`TransferRequest::total_bytes`] before accepting.
+    pub fn free_space(&self) -> Option<u64> {
+        space::available(&self.inner.download_dir()?)
+    }
+
+    /// The names of devices transfers were accepted from, if
+    /// [`Config::trust_store`] is set.
+    pub fn trust_store(&self) -> Option<&TrustStore> {
+        self.inner.trust_store.as_ref()
+    }
+
+    /// Whether `request`'s sender goes by the name of a device a transfer was
+    /// accepted from before. Any device can take that name, so this doesn't
+    /// tell who's sending. Always `false` without a [`Config::trust_store`].
+    pub fn is_trusted(&self, request: &TransferRequest) -> bool {
+        self.inner
+            .trust_store
+            .as_ref()
+            .is_some_and(|t| t.trusts(request))
+    }
+
+    /// Decides on inbound requests as they arrive, e.g. to accept transfers
+    /// from known devices without asking. Requests the policy accepts or
+    /// rejects never reach [`get_transfer_requests`](Self::get_transfer_requests).
+    /// Replaces any previous policy.
+    pub fn set_accept_policy(
+        &self,
+        policy: impl Fn(&TransferRequest) -> Decision + Send + Sync + 'static,
+    ) {
+        *self
+            .inner
+            .accept_policy
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(policy));
+    }
+
+    pub async fn reject_transfer(&self, request: &TransferRequest) -> Result<()> {
+        self.reject_transfer_with_reason(request, RejectReason::Declined)
+            .await
+    }
+
+    /// Like [`reject_transfer`](Self::reject_transfer), recording `reason`
+    /// in [`OxidropEvent::TransferRejected`] and the history.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = request.id(), ?reason)))]
+    pub async fn reject_transfer_with_reason(
+        &self,
+        request: &TransferRequest,
+        reason: RejectReason,
+    ) -> Result<()> {
+        self.inner.reject(request, reason)?;
+        self.inner
+            .pending
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .shift_remove(request.id());
+
+        Ok(())
+    }
+
+    /// Cancels an outbound transfer and returns the state it ended in. That's
+    /// usually [`TransferState::Cancelled`], but a transfer that ended first
+    /// keeps its outcome.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = handle.id())))]
+    pub async fn cancel_transfer(&self, handle: &TransferHandle) -> Result<TransferState> {
+        self.inner.cancel(handle.id()).await
+    }
+
+    /// The code the receiver shows for an outbound transfer, like
+    /// [`TransferRequest::pin_code`] on its side. `None` until the devices
+    /// have finished their handshake, which is before the receiver is asked
+    /// to accept.
+    pub fn pin_code(&self, handle: &TransferHandle) -> Option<String> {
+        self.inner.pin_codes.lock().ok()?.get(handle.id()).cloned()
+    }
+
+    /// Outbound transfers waiting for one of the
+    /// [`Config::max_concurrent_sends`] slots, next to start first.
+    pub fn pending_sends(&self) -> Result<Vec<PendingSend>> {
+        Ok(self
+            .inner
+            .send_queue
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .waiting()
+            .map(|w| PendingSend {
+                handle: TransferHandle {
+                    id: w.info.id.clone(),
+                },
+                receiver: w.info.name.clone(),
+                priority: w.priority,
+            })
+            .collect())
+    }
+
+    /// Takes a send out of the queue before it starts.
+    pub fn cancel_pending(&self, handle: &TransferHandle) -> Result<()> {
+        if !self.inner.cancel_queued(handle.id())? {
+            return Err(Error::UnknownTransfer(handle.id().to_string()));
+        }
+        Ok(())
+    }
+
+    /// How many queued transfers start before this one, or `None` if it
+    /// isn't waiting.
+    pub fn queue_position(&self, handle: &TransferHandle) -> Option<usize> {
+        self.inner.send_queue.lock().ok()?.position(handle.id())
+    }
+
+    /// Moves a queued transfer to `position` in the queue, 0 being next.
+    pub fn move_in_queue(&self, handle: &TransferHandle, position: usize) -> Result<()> {
+        let moved = self
+            .inner
+            .send_queue
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .move_to(handle.id(), position);
+        if !moved {
+            return Err(Error::UnknownTransfer(handle.id().to_string()));
+        }
+        Ok(())
+    }
+
+    /// SHA-256 digests of the files an outbound transfer sends, to compare
+    /// with the receiver's. Works whether or not [`Config::checksums`] is set.
+    pub async fn sent_checksums(&self, handle: &TransferHandle) -> Result<Vec<FileDigest>> {
+        let paths = self
+            .inner
+            .outgoing
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get(handle.id())
+            .map(|t| t.files.clone())
+            .ok_or_else(|| Error::UnknownTransfer(handle.id().to_string()))?;
+        Ok(checksum::digest_files(paths).await)
+    }
+
+    /// Transfers recorded in [`Config::history`] that match `filter`, oldest
+    /// first. Empty if no history file is set.
+    #[cfg(feature = "history")]
+    pub fn history(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>> {
+        match &self.inner.history {
+            Some(history) => history.query(filter),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Waits for an outbound transfer to end and tells how it did, e.g. to
+    /// tell a receiver declining apart from it going away. Fails with
+    /// [`Error::UnknownTransfer`] if `handle` isn't from this instance, or
+    /// ended too long ago to be remembered.
+    pub async fn outcome(&self, handle: &TransferHandle) -> Result<TransferOutcome> {
+        let mut event_recv = self.inner.event_send.subscribe();
+        loop {
+            let state = self
+                .inner
+                .transfers
+                .lock()
+                .map_err(|_| Error::CorruptedState)?
+                .get(handle.id())
+                .copied();
+            if let Some(outcome) = state.and_then(TransferOutcome::from_state) {
+                return Ok(outcome);
+            }
+            // Sends are known from the moment they're made, queued or not.
+            if state.is_none()
+                && !self
+                    .inner
+                    .outgoing
+                    .lock()
+                    .map_err(|_| Error::CorruptedState)?
+                    .contains_key(handle.id())
+            {
+                return Err(Error::UnknownTransfer(handle.id().to_string()));
+            }
+            match event_recv.recv().await {
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Err(Error::CorruptedState),
+            }
+        }
+    }
+
+    /// Sends what a failed or cancelled outbound transfer didn't get to
+    /// again, skipping the files it sent completely. The receiver sees a new
+    /// transfer, and the file that was cut off is sent from its beginning,
+    /// since Quick Share can't continue one.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = handle.id())))]
+    pub async fn resume(&self, handle: &TransferHandle) -> Result<TransferHandle> {
+        let state = self
+            .inner
+            .transfers
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get(handle.id())
+            .copied();
+        if !state.is_some_and(|s| s.is_final() && s != TransferState::Done) {
+            return Err(Error::CannotResume(handle.id().to_string()));
+        }
+        let (endpoint, files) = self
+            .inner
+            .outgoing
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get(handle.id())
+            .map(|t| (t.endpoint.clone(), t.unsent_files()))
+            .ok_or_else(|| Error::UnknownTransfer(handle.id().to_string()))?;
+        if files.is_empty() {
+            return Err(Error::CannotResume(handle.id().to_string()));
+        }
+
+        let files = files.into_iter().map(|path| File {
+            path,
+            ..Default::default()
+        });
+        self.send_files(&endpoint, files).await
+    }
+
+    /// Aborts an inbound transfer that was accepted, deleting the file that
+    /// was being written, and returns the state it ended in like
+    /// [`cancel_transfer`](Self::cancel_transfer). Files received completely
+    /// are kept.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = request.id())))]
+    pub async fn cancel_incoming(&self, request: &TransferRequest) -> Result<TransferState> {
+        let state = self.inner.cancel(request.id()).await?;
+        let set_aside = |path: &Path| {
+            self.inner
+                .incoming
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(request.id())
+                .is_some_and(|t| t.was_set_aside(path))
+        };
+        if state != TransferState::Done
+            && let Ok(files) = self.incoming_files(request.id())
+        {
+            // Files that were copied aside are put back when the transfer ends.
+            for file in files
+                .iter()
+                .filter(|f| !f.is_complete() && !set_aside(f.path()))
+            {
+                if let Err(e) = std::fs::remove_file(file.path()) {
+                    log::warn!("Failed to remove {}: {e}", file.path().display());
+                }
+            }
+        }
+        Ok(state)
+    }
+
+    /// Every transfer that has started and not ended, including inbound
+    /// requests waiting for an answer, in no particular order. Sends still
+    /// in the queue are listed by [`pending_sends`](Self::pending_sends).
+    pub fn active_transfers(&self) -> Result<Vec<ActiveTransfer>> {
+        let transfers = self
+            .inner
+            .transfers
+            .lock()
+            .map_err(|_| Error::CorruptedState)?;
+        let details = self
+            .inner
+            .details
+            .lock()
+            .map_err(|_| Error::CorruptedState)?;
+        Ok(transfers
+            .iter()
+            .filter(|(_, state)| !state.is_final())
+            .filter_map(|(id, state)| {
+                let d = details.get(id)?;
+                Some(ActiveTransfer {
+                    id: id.clone(),
+                    direction: d.direction,
+                    peer: d.peer.clone(),
+                    state: *state,
+                    progress: d.progress,
+                })
+            })
+            .collect())
+    }
+
+    /// Bytes moved, transfers ended and current throughput since this
+    /// instance was created.
+    pub fn metrics(&self) -> Result<Metrics> {
+        let active = self.inner.active_transfers().len();
+        Ok(self
+            .inner
+            .metrics
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .snapshot(active))
+    }
+
+    /// Whether any transfer is in progress, which is worth asking about before
+    /// shutting down.
+    pub fn has_active_transfers(&self) -> bool {
+        !self.inner.active_transfers().is_empty()
+    }
+
+    /// Asks every transfer in progress to cancel. Use
+    /// [`wait_for_transfers`](Self::wait_for_transfers) to know when they
+    /// have.
+    pub fn cancel_all_transfers(&self) -> Result<()> {
+        for id in self.inner.active_transfers() {
+            // Queued sends go first, so none starts in a slot freed here.
+            if !self.inner.cancel_queued(&id)? {
+                self.inner
+                    .send_action_to(&id, ChannelAction::CancelTransfer)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns once no transfer is in progress.
+    pub async fn wait_for_transfers(&self) {
+        let mut event_recv = self.inner.event_send.subscribe();
+        while self.has_active_transfers() {
+            match event_recv.recv().await {
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// The files of inbound transfer `id` that have started arriving, which
+    /// can be read while the transfer is still running.
+    ///
+    /// Each file is saved under the name the sender gave it, over any existing
+    /// one, until the transfer is done and [`Config::overwrite_policy`] is
+    /// applied. Existing files are kept in a hidden copy until then.
+    pub fn incoming_files(&self, id: &str) -> Result<Vec<IncomingFile>> {
+        let transfer = self
+            .inner
+            .incoming
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::UnknownTransfer(id.to_string()))?;
+        let done = self
+            .inner
+            .transfers
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get(id)
+            .is_some_and(|&state| state == TransferState::Done);
+        Ok(transfer.files(done))
+    }
+
+    /// How long it takes to open a connection to `endpoint`, without starting
+    /// a transfer. Fails with [`Error::EndpointUnreachable`] if it can't be
+    /// opened within a few seconds, for endpoints that are still advertised
+    /// but gone.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(endpoint = endpoint.name())))]
+    pub async fn probe(&self, endpoint: &Endpoint) -> Result<Duration> {
+        connect_time(endpoint)
+            .await
+            .ok_or_else(|| Error::EndpointUnreachable(endpoint.name().to_string()))
+    }
+
+    /// Sends `files` to `endpoint`.
+    ///
+    /// If the connection to a discovered endpoint fails before the transfer
+    /// gets going, it is looked up again by id and the send is retried once
+    /// at its new address, emitting [`OxidropEvent::EndpointAddressChanged`].
+    pub async fn send_files(
+        &self,
+        endpoint: &Endpoint,
+        files: impl Iterator<Item = File>,
+    ) -> Result<TransferHandle> {
+        self.send_files_with_priority(endpoint, files, 0).await
+    }
+
+    /// Sends the same `files` to each of `endpoints` at once. Returns the
+    /// outcome of starting each send, in the order of `endpoints`; see
+    /// [`combined_progress`](Self::combined_progress) to follow them all.
+    pub async fn send_files_to_many(
+        &self,
+        endpoints: &[&Endpoint],
+        files: impl IntoIterator<Item = File>,
+    ) -> Vec<Result<TransferHandle>> {
+        // Staged once, so every send shares the same links.
+        let files = match self.stage(files.into_iter()) {
+            Ok(files) => files,
+            Err(e) => {
+                return endpoints
+                    .iter()
+                    .map(|_| match &e {
+                        Error::InvalidFileName(name) => Err(Error::InvalidFileName(name.clone())),
+                        e => Err(Error::Other(e.to_string().into())),
+                    })
+                    .collect();
+            }
+        };
+        let sends: Vec<_> = endpoints
+            .iter()
+            .map(|endpoint| {
+                let oxidrop = self.clone();
+                let endpoint = (*endpoint).clone();
+                let files = files.clone();
+                tokio::spawn(async move { oxidrop.send_staged(&endpoint, files, 0).await })
+            })
+            .collect();
+
+        let mut handles = Vec::with_capacity(sends.len());
+        for send in sends {
+            handles.push(
+                send.await
+                    .unwrap_or_else(|e| Err(Error::Other(Box::new(e)))),
+            );
+        }
+        handles
+    }
+
+    /// Progress of all of `handles` together, as from
+    /// [`send_files_to_many`](Self::send_files_to_many). The stream ends once
+    /// every one of them has.
+    pub fn combined_progress(
+        &self,
+        handles: &[TransferHandle],
+    ) -> impl Stream<Item = Progress> + use<> {
+        let mut latest: HashMap<String, Progress> = {
+            let outgoing = self
+                .inner
+                .outgoing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            handles
+                .iter()
+                .map(|h| {
+                    let total = outgoing.get(h.id()).map_or(0, |o| o.sizes.iter().sum());
+                    let progress = Progress {
+                        transferred: 0,
+                        total,
+                        file_index: None,
+                    };
+                    (h.id().to_string(), progress)
+                })
+                .collect()
+        };
+        let mut running: HashSet<String> = {
+            let transfers = self
+                .inner
+                .transfers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            latest
+                .keys()
+                .filter(|id| !transfers.get(*id).is_some_and(|s| s.is_final()))
+                .cloned()
+                .collect()
+        };
+
+        // The leading `None` ends the stream right away if nothing is running.
+        tokio_stream::once(None)
+            .chain(
+                BroadcastStream::new(self.inner.event_send.subscribe())
+                    .filter_map(|r| r.ok())
+                    .map(Some),
+            )
+            .map_while(move |event| {
+                match event {
+                    Some(OxidropEvent::TransferProgress { id, progress, .. }) => {
+                        if let Some(p) = latest.get_mut(&id) {
+                            *p = progress;
+                            return Some(Some(Progress {
+                                transferred: latest.values().map(|p| p.transferred).sum(),
+                                total: latest.values().map(|p| p.total).sum(),
+                                file_index: None,
+                            }));
+                        }
+                    }
+                    Some(OxidropEvent::TransferStateChanged { id, state, .. })
+                        if state.is_final() =>
+                    {
+                        running.remove(&id);
+                    }
+                    _ => {}
+                }
+                (!running.is_empty()).then_some(None)
+            })
+            .filter_map(|p| p)
+    }
+
+    /// Like [`send_files`](Self::send_files), but if the send has to wait
+    /// for a slot, it's queued ahead of sends with a lower `priority`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(endpoint = endpoint.name(), priority)))]
+    pub async fn send_files_with_priority(
+        &self,
+        endpoint: &Endpoint,
+        files: impl Iterator<Item = File>,
+        priority: i32,
+    ) -> Result<TransferHandle> {
+        let files = self.stage(files)?;
+        self.send_staged(endpoint, files, priority).await
+    }
+
+    /// The paths to hand to `rqs_lib` so that `files` arrive under their
+    /// [`File::wire_name`]s.
+    fn stage(&self, files: impl Iterator<Item = File>) -> Result<Vec<String>> {
+        files
+            .map(|f| {
+                self.inner
+                    .staging
+                    .prepare(&f)
+                    .map(|p| p.to_string_lossy().into_owned())
+            })
+            .collect()
+    }
+
+    async fn send_staged(
+        &self,
+        endpoint: &Endpoint,
+        files: Vec<String>,
+        priority: i32,
+    ) -> Result<TransferHandle> {
+        self.inner.ensure_started().await?;
+
+        // `rqs_lib` reports the transfer under whatever id it's handed, so
+        // each send gets its own, even when several go to one device.
+        let id = format!(
+            "{}-{}",
+            endpoint.0.id,
+            self.inner.next_send.fetch_add(1, Ordering::Relaxed)
+        );
+        let sizes = files
+            .iter()
+            .map(|f| std::fs::metadata(f).map_or(0, |m| m.len()))
+            .collect();
+        self.inner
+            .outgoing
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .insert(
+                id.clone(),
+                OutgoingTransfer {
+                    endpoint: endpoint.clone(),
+                    files: files.iter().map(PathBuf::from).collect(),
+                    sizes,
+                    transferred: 0,
+                    retried: false,
+                },
+            );
+        let info = SendInfo {
+            id: id.clone(),
+            name: endpoint.name().to_string(),
+            addr: endpoint.addr(),
+            ob: OutboundPayload::Files(files),
+        };
+        let limit = self.inner.config().max_concurrent_sends;
+        let info = self
+            .inner
+            .send_queue
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .push(info, priority, limit);
+        if let Some(info) = info
+            && let Err(e) = self.inner.dispatch(info).await
+        {
+            self.inner
+                .outgoing
+                .lock()
+                .map_err(|_| Error::CorruptedState)?
+                .remove(&id);
+            self.inner.release_slot(&id);
+            return Err(e);
+        }
+
+        Ok(TransferHandle { id })
+    }
+
+    async fn resolve_again(&self, endpoint: &Endpoint) -> Result<Endpoint> {
+        let mut endpoints = self.discover_endpoints().await?;
+        timeout(REDISCOVERY_TIMEOUT, async {
+            while let Some(e) = endpoints.next().await {
+                if e == *endpoint && e.addr() != endpoint.addr() {
+                    return Some(e);
+                }
+            }
+            None
+        })
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| Error::EndpointUnreachable(endpoint.name().to_string()))
+    }
+
+    /// Progress of transfer `id`, which is [`TransferRequest::id`] or
+    /// [`TransferHandle::id`]. The stream ends once the transfer does, and is
+    /// empty if it already has or isn't known to this instance.
+    pub fn transfer_progress(&self, id: &str) -> impl Stream<Item = Progress> + use<> {
+        let event_recv = self.inner.event_send.subscribe();
+        let state = self
+            .inner
+            .transfers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .copied();
+        let running = match state {
+            Some(state) => !state.is_final(),
+            // Sends are known before `rqs_lib` first reports them.
+            None => self
+                .inner
+                .outgoing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains_key(id),
+        };
+        let id = id.to_string();
+        let until = id.clone();
+        BroadcastStream::new(event_recv)
+            .take(if running { usize::MAX } else { 0 })
+            .filter_map(|r| r.ok())
+            .take_while(move |event| {
+                !matches!(
+                    event,
+                    OxidropEvent::TransferStateChanged { id, state, .. }
+                        if *id == until && state.is_final()
+                )
+            })
+            .filter_map(move |event| match event {
+                OxidropEvent::TransferProgress {
+                    id: event_id,
+                    progress,
+                    ..
+                } if event_id == id => Some(progress),
+                _ => None,
+            })
+    }
+
+    /// The states an inbound transfer goes through, starting with the one
+    /// it's in now. The stream ends after the transfer reaches a final state,
+    /// and is empty if `request` isn't from this instance or ended too long
+    /// ago to be remembered.
+    pub fn watch(&self, request: &TransferRequest) -> impl Stream<Item = TransferState> + use<> {
+        let mut event_recv = self.inner.event_send.subscribe();
+        let current = self
+            .inner
+            .transfers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(request.id())
+            .copied();
+
+        // Forwarding from a task lets the stream end as soon as the final
+        // state is out, instead of on whatever event comes next.
+        let (state_send, state_recv) = mpsc::channel(8);
+        let Some(mut current) = current else {
+            return ReceiverStream::new(state_recv);
+        };
+        let id = request.id().to_string();
+        tokio::spawn(async move {
+            loop {
+                if state_send.send(current).await.is_err() || current.is_final() {
+                    return;
+                }
+                current = loop {
+                    let event = tokio::select! {
+                        r = event_recv.recv() => r,
+                        () = state_send.closed() => return,
+                    };
+                    match event {
+                        Ok(OxidropEvent::TransferStateChanged {
+                            id: event_id,
+                            state,
+                            ..
+                        }) if event_id == id && state != current => break state,
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    }
+                };
+            }
+        });
+        ReceiverStream::new(state_recv)
+    }
+
+    /// Every [`OxidropEvent`] from now on. Subscribers that fall behind skip
+    /// the events they missed; see [`checked_events`](Self::checked_events)
+    /// to be told.
+    pub fn events(&self) -> impl Stream<Item = OxidropEvent> + use<> {
+        BroadcastStream::new(self.inner.event_send.subscribe()).filter_map(|r| r.ok())
+    }
+
+    /// Like [`events`](Self::events), but with an [`Error::EventsDropped`]
+    /// where events were missed because the subscriber fell more than
+    /// [`Config::channel_capacity`] events behind.
+    pub fn checked_events(&self) -> impl Stream<Item = Result<OxidropEvent>> + use<> {
+        BroadcastStream::new(self.inner.event_send.subscribe())
+            .map(|r| r.map_err(|BroadcastStreamRecvError::Lagged(n)| Error::EventsDropped(n)))
+    }
+
+    /// Calls `observer` for every [`OxidropEvent`] from now on, like
+    /// [`events`](Self::events) does for streams.
+    pub fn register_observer(&self, observer: impl TransferObserver) -> ObserverHandle {
+        observer::register(self.inner.event_send.subscribe(), observer)
+    }
+
+    /// Like [`register_observer`](Self::register_observer), but `observer` is
+    /// called from a thread outside the runtime, so it may block on calls
+    /// into this instance. This suits callbacks into other languages.
+    pub fn register_blocking_observer(&self, observer: impl TransferObserver) -> ObserverHandle {
+        observer::register_on_thread(self.inner.event_send.subscribe(), observer)
+    }
+
+    /// Nearby endpoints, starting with the ones already known. Discovery runs
+    /// as long as any of these streams is alive.
+    ///
+    /// With [`Config::static_peers`] set, yields those instead, without
+    /// announcing them with [`OxidropEvent::EndpointDiscovered`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn discover_endpoints(&self) -> Result<impl Stream<Item = Endpoint> + use<>> {
+        self.inner.ensure_started().await?;
+
+        if let Some(peers) = self.inner.config().static_peers {
+            let endpoints = peers
+                .iter()
+                .map(|p| Endpoint::from_addr(p.addr, &p.name))
+                .collect::<Vec<_>>();
+            let endpoints: Pin<Box<dyn Stream<Item = Endpoint> + Send>> =
+                Box::pin(tokio_stream::iter(endpoints).chain(tokio_stream::pending()));
+            return Ok(endpoints);
+        }
+
+        #[pin_project(PinnedDrop)]
+        struct StreamWrapper<S: Stream<Item = Endpoint>>(
+            #[pin] S,
+            Weak<Inner>,
+            broadcast::WeakSender<EndpointInfo>,
+        );
+
+        impl<S: Stream<Item = Endpoint>> Stream for StreamWrapper<S> {
+            type Item = Endpoint;
+
+            fn poll_next(
+                self: Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Option<Self::Item>> {
+                self.project().0.poll_next(cx)
+            }
+        }
+
+        #[pinned_drop]
+        impl<S: Stream<Item = Endpoint>> PinnedDrop for StreamWrapper<S> {
+            fn drop(self: Pin<&mut Self>) {
+                if let Some(inner) = self.1.upgrade()
+                    && let Some(sender) = self.2.upgrade()
+                    && sender.receiver_count() == 1
+                {
+                    inner
+                        .backend
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .stop_discovery();
+                    inner
+                        .endpoints
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .clear();
+                }
+            }
+        }
+
+        let (endpoint_send, endpoint_recv) = {
+            let mut endpoint_send_guard = self
+                .inner
+                .endpoint_send
+                .lock()
+                .map_err(|_| Error::CorruptedState)?;
+
+            match endpoint_send_guard.upgrade() {
+                Some(endpoint_send) => (endpoint_send.downgrade(), endpoint_send.subscribe()),
+                None => {
+                    let (endpoint_send, endpoint_recv) =
+                        broadcast::channel(self.inner.channel_capacity);
+                    *endpoint_send_guard = endpoint_send.downgrade();
+                    self.inner
+                        .backend
+                        .lock()
+                        .map_err(|_| Error::CorruptedState)?
+                        .discover(endpoint_send.clone())?;
+                    (endpoint_send.downgrade(), endpoint_recv)
+                }
+            }
+        };
+
+        let cached = self.inner.cached_endpoints()?;
+        let inner = Arc::downgrade(&self.inner);
+        // Devices announce themselves over and over, so each stream passes an
+        // endpoint on only when it's new to it or has changed.
+        let mut seen: HashMap<_, _> = cached.iter().map(|e| (e.0.id.clone(), e.clone())).collect();
+        Ok(Box::pin(StreamWrapper(
+            tokio_stream::iter(cached).chain(BroadcastStream::new(endpoint_recv).filter_map(
+                move |r| {
+                    let info = r.ok()?;
+                    let inner = inner.upgrade();
+                    if info.present == Some(false) {
+                        seen.remove(&info.id);
+                        if let Some(inner) = inner {
+                            inner.forget_endpoint(&info.id);
+                        }
+                        return None;
+                    }
+
+                    let endpoint = Some(info)
+                        .filter(|e| e.ip.is_some() && e.port.is_some())
+                        .map(Endpoint)?;
+                    // Endpoints that expired from the cache count as new again.
+                    let rediscovered = inner.is_some_and(|i| i.remember_endpoint(&endpoint));
+                    match seen.insert(endpoint.0.id.clone(), endpoint.clone()) {
+                        Some(previous) if !rediscovered && !previous.changed_in(&endpoint) => None,
+                        _ => Some(endpoint),
+                    }
+                },
+            )),
+            Arc::downgrade(&self.inner),
+            endpoint_send,
+        )))
+    }
+
+    /// Like [`discover_endpoints`](Self::discover_endpoints), but also tells
+    /// when endpoints change or go away, so lists of devices can be kept
+    /// current.
+    pub async fn discover_endpoint_updates(
+        &self,
+    ) -> Result<impl Stream<Item = EndpointEvent> + use<>> {
+        let mut lost = self.events().filter_map(|e| match e {
+            OxidropEvent::EndpointLost(endpoint) => Some(endpoint),
+            _ => None,
+        });
+        let mut endpoints = self.discover_endpoints().await?;
+        let inner = Arc::downgrade(&self.inner);
+
+        // The task owns the discovery stream, so discovery stops once the
+        // returned stream is dropped.
+        let (update_send, update_recv) = mpsc::channel(10);
+        tokio::spawn(async move {
+            let mut known = HashMap::<String, Endpoint>::new();
+            let mut expiry = tokio::time::interval(ENDPOINT_TTL / 4);
+            loop {
+                let update = tokio::select! {
+                    Some(endpoint) = endpoints.next() => {
+                        match known.insert(endpoint.0.id.clone(), endpoint.clone()) {
+                            None => EndpointEvent::Added(endpoint),
+                            Some(previous) if previous.changed_in(&endpoint) => {
+                                EndpointEvent::Updated(endpoint)
+                            }
+                            Some(_) => continue,
+                        }
+                    }
+                    Some(endpoint) = lost.next() => {
+                        match known.remove(&endpoint.0.id) {
+                            Some(_) => EndpointEvent::Removed(endpoint),
+                            None => continue,
+                        }
+                    }
+                    _ = expiry.tick() => {
+                        match inner.upgrade() {
+                            Some(inner) => inner.expire_endpoints(),
+                            None => return,
+                        }
+                        continue;
+                    }
+                    () = update_send.closed() => return,
+                    else => return,
+                };
+                if update_send.send(update).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(ReceiverStream::new(update_recv))
+    }
+
+    /// Returns the inbound requests waiting for consent, starting with the ones
+    /// that arrived before this call. Requests answered by policy aren't
+    /// included.
+    pub fn get_transfer_requests(&self) -> Result<impl Stream<Item = TransferRequest> + use<>> {
+        #[pin_project(PinnedDrop)]
+        struct StreamWrapper<S: Stream<Item = TransferRequest>>(#[pin] S, Weak<Inner>);
+
+        impl<S: Stream<Item = TransferRequest>> Stream for StreamWrapper<S> {
+            type Item = TransferRequest;
+
+            fn poll_next(
+                self: Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Option<Self::Item>> {
+                self.project().0.poll_next(cx)
+            }
+        }
+
+        #[pinned_drop]
+        impl<S: Stream<Item = TransferRequest>> PinnedDrop for StreamWrapper<S> {
+            fn drop(self: Pin<&mut Self>) {
+                if let Some(inner) = self.1.upgrade() {
+                    let mut request_send =
+                        inner.request_send.lock().unwrap_or_else(|e| e.into_inner());
+                    if request_send
+                        .as_ref()
+                        .is_some_and(|s| s.receiver_count() == 1)
+                    {
+                        *request_send = None;
+                    }
+                }
+            }
+        }
+
+        if self.inner.config().send_only {
+            return Err(Error::ReceivingDisabled);
+        }
+        // Both locked at once, so no request is missed or seen twice.
+        let pending = self
+            .inner
+            .pending
+            .lock()
+            .map_err(|_| Error::CorruptedState)?;
+        let request_recv = self
+            .inner
+            .request_send
+            .lock()
+            .map_err(|_| Error::CorruptedState)?
+            .get_or_insert_with(|| broadcast::channel(self.inner.channel_capacity).0)
+            .subscribe();
+        let pending: Vec<_> = pending.values().cloned().collect();
+
+        Ok(StreamWrapper(
+            tokio_stream::iter(pending)
+                .chain(BroadcastStream::new(request_recv).filter_map(|r| r.ok())),
+            Arc::downgrade(&self.inner),
+        ))
+    }
+}
+
+/// A port nothing listens on right now, to listen on. Something else may
+/// take it before then, which [`start_backend`] handles.
+fn free_port() -> Result<u16> {
+    std::net::TcpListener::bind((std::net::Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|l| l.local_addr())
+        .map(|a| a.port())
+        .map_err(|e| Error::Other(Box::new(e)))
+}
+
+/// Whether the service failed to start because its port was taken.
+fn is_addr_in_use(e: &Error) -> bool {
+    let Error::Other(e) = e else {
+        return false;
+    };
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(e.as_ref());
+    while let Some(e) = source {
+        if e.downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::AddrInUse)
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+async fn connect_time(endpoint: &Endpoint) -> Option<Duration> {
+    let start = Instant::now();
+    match timeout(CONNECT_TIMEOUT, TcpStream::connect(endpoint.addr())).await {
+        Ok(Ok(_)) => Some(start.elapsed()),
+        _ => None,
+    }
+}
@@ -0,0 +1,320 @@
+//! A [`ShareBackend`] that moves transfers between instances in the same
+//! process without touching the network, for
+//! [`fixtures::loopback_pair`](crate::fixtures::loopback_pair).
+//!
+//! It reports transfers with the messages `rqs_lib` would, so everything above
+//! the backend behaves as it does against real devices.
+
+use std::{
+    collections::HashMap,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use rqs_lib::{
+    DeviceType, EndpointInfo, OutboundPayload, SendInfo, State, Visibility,
+    channel::{ChannelAction, ChannelDirection, ChannelMessage, TransferType},
+    hdl::info::{RemoteDeviceInfo, TransferMetadata},
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::AbortHandle,
+};
+
+use crate::{
+    Endpoint, Result,
+    backend::{BoxFuture, ShareBackend},
+};
+
+/// The instances that can reach each other, by the address they listen on.
+#[derive(Default)]
+pub(crate) struct Network {
+    stations: Mutex<HashMap<SocketAddr, Station>>,
+    next_transfer: AtomicU64,
+    /// Whether accepted transfers stop before their first byte, until one
+    /// side cancels them.
+    pub(crate) stall: AtomicBool,
+}
+
+/// One instance as the others see it.
+#[derive(Clone)]
+struct Station {
+    name: String,
+    messages: broadcast::Sender<ChannelMessage>,
+    download_dir: Arc<Mutex<Option<PathBuf>>>,
+    /// Where answers and cancellations for transfers this instance is part of
+    /// go, by the id it knows them under.
+    actions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<ChannelAction>>>>,
+}
+
+pub(crate) struct MemoryBackend {
+    network: Arc<Network>,
+    addr: SocketAddr,
+    station: Station,
+    task: Option<AbortHandle>,
+}
+
+impl MemoryBackend {
+    /// An instance reachable at `addr` on `network`, which others know as
+    /// `name`, once started.
+    pub(crate) fn new(
+        network: Arc<Network>,
+        addr: SocketAddr,
+        name: &str,
+        download_dir: Option<PathBuf>,
+    ) -> Self {
+        MemoryBackend {
+            network,
+            addr,
+            station: Station {
+                name: name.to_string(),
+                messages: broadcast::channel(16).0,
+                download_dir: Arc::new(Mutex::new(download_dir)),
+                actions: Arc::default(),
+            },
+            task: None,
+        }
+    }
+}
+
+impl ShareBackend for MemoryBackend {
+    fn start(&mut self) -> BoxFuture<'_, Result<mpsc::Sender<SendInfo>>> {
+        Box::pin(async move {
+            let (sendinfo_send, mut sendinfo_recv) = mpsc::channel(16);
+            self.network
+                .stations
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(self.addr, self.station.clone());
+            let network = self.network.clone();
+            let from = self.station.clone();
+            let task = tokio::spawn(async move {
+                while let Some(info) = sendinfo_recv.recv().await {
+                    tokio::spawn(network.clone().deliver(from.clone(), info));
+                }
+            });
+            self.task = Some(task.abort_handle());
+            Ok(sendinfo_send)
+        })
+    }
+
+    fn stop(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Some(task) = self.task.take() {
+                task.abort();
+            }
+            self.network
+                .stations
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&self.addr);
+        })
+    }
+
+    fn messages(&self) -> broadcast::Receiver<ChannelMessage> {
+        self.station.messages.subscribe()
+    }
+
+    fn act(&self, id: &str, action: ChannelAction) -> Result<()> {
+        if let Some(actions) = self
+            .station
+            .actions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+        {
+            let _ = actions.send(action);
+        }
+        Ok(())
+    }
+
+    fn advertise(&mut self, _visibility: Visibility) {}
+
+    fn discover(&mut self, sender: broadcast::Sender<EndpointInfo>) -> Result<()> {
+        let stations = self
+            .network
+            .stations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        for (addr, station) in stations.iter().filter(|(addr, _)| **addr != self.addr) {
+            let _ = sender.send(Endpoint::from_addr(*addr, &station.name).0);
+        }
+        Ok(())
+    }
+
+    fn stop_discovery(&mut self) {}
+
+    fn set_download_dir(&mut self, dir: Option<PathBuf>) {
+        *self
+            .station
+            .download_dir
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = dir;
+    }
+}
+
+impl Network {
+    /// Offers `info` from `from` to whoever listens on its address, and copies
+    /// the files over once it's accepted.
+    async fn deliver(self: Arc<Self>, from: Station, info: SendInfo) {
+        let OutboundPayload::Files(paths) = info.ob;
+        let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+        let names: Vec<String> = paths
+            .iter()
+            .map(|p| {
+                p.file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        let sizes: Vec<u64> = paths
+            .iter()
+            .map(|p| fs::metadata(p).map_or(0, |m| m.len()))
+            .collect();
+        let total_bytes = sizes.iter().sum();
+
+        let to = info.addr.parse().ok().and_then(|addr| {
+            self.stations
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&addr)
+                .cloned()
+        });
+        let inbound_id = format!(
+            "memory-{}",
+            self.next_transfer.fetch_add(1, Ordering::Relaxed)
+        );
+        let outbound = |state, ack_bytes| ChannelMessage {
+            id: info.id.clone(),
+            direction: ChannelDirection::LibToFront,
+            action: None,
+            rtype: Some(TransferType::Outbound),
+            state: Some(state),
+            meta: Some(TransferMetadata {
+                id: info.id.clone(),
+                destination: to.as_ref().map(|to| to.name.clone()),
+                files: Some(names.clone()),
+                pin_code: Some(PIN_CODE.to_string()),
+                total_bytes,
+                ack_bytes,
+                ..Default::default()
+            }),
+        };
+        let inbound = |state, ack_bytes| ChannelMessage {
+            id: inbound_id.clone(),
+            direction: ChannelDirection::LibToFront,
+            action: None,
+            rtype: Some(TransferType::Inbound),
+            state: Some(state),
+            meta: Some(TransferMetadata {
+                id: inbound_id.clone(),
+                source: Some(RemoteDeviceInfo {
+                    name: from.name.clone(),
+                    device_type: DeviceType::Laptop,
+                }),
+                files: Some(names.clone()),
+                pin_code: Some(PIN_CODE.to_string()),
+                total_bytes,
+                ack_bytes,
+                ..Default::default()
+            }),
+        };
+        let Some(to) = to.clone() else {
+            let _ = from.messages.send(outbound(State::Disconnected, 0));
+            return;
+        };
+        let both = |state: State, ack_bytes| {
+            let _ = from.messages.send(outbound(state.clone(), ack_bytes));
+            let _ = to.messages.send(inbound(state, ack_bytes));
+        };
+
+        let (action_send, mut action_recv) = mpsc::unbounded_channel();
+        for (station, id) in [(&from, &info.id), (&to, &inbound_id)] {
+            station
+                .actions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(id.clone(), action_send.clone());
+        }
+        drop(action_send);
+
+        let _ = from.messages.send(outbound(State::SentIntroduction, 0));
+        let _ = to.messages.send(inbound(State::WaitingForUserConsent, 0));
+        let end = match action_recv.recv().await {
+            Some(ChannelAction::AcceptTransfer) if self.stall.load(Ordering::Relaxed) => {
+                let _ = from.messages.send(outbound(State::SendingFiles, 0));
+                let _ = to.messages.send(inbound(State::ReceivingFiles, 0));
+                action_recv.recv().await;
+                State::Cancelled
+            }
+            Some(ChannelAction::AcceptTransfer) => {
+                let dir = to
+                    .download_dir
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone()
+                    .or_else(|| {
+                        directories::UserDirs::new()
+                            .and_then(|d| d.download_dir().map(Path::to_path_buf))
+                    });
+                copy(&paths, &names, &sizes, dir, &mut action_recv, |ack_bytes| {
+                    let _ = from.messages.send(outbound(State::SendingFiles, ack_bytes));
+                    let _ = to.messages.send(inbound(State::ReceivingFiles, ack_bytes));
+                })
+            }
+            Some(ChannelAction::RejectTransfer) => State::Rejected,
+            Some(ChannelAction::CancelTransfer) | None => State::Cancelled,
+        };
+        let ack_bytes = if matches!(end, State::Finished) {
+            total_bytes
+        } else {
+            0
+        };
+        both(end, ack_bytes);
+
+        for (station, id) in [(&from, &info.id), (&to, &inbound_id)] {
+            station
+                .actions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(id);
+        }
+    }
+}
+
+/// The code both sides of every transfer are shown.
+const PIN_CODE: &str = "1234";
+
+/// Copies `paths` into `dir` under `names`, reporting the bytes copied after
+/// each file. Returns the state the transfer ended in.
+fn copy(
+    paths: &[PathBuf],
+    names: &[String],
+    sizes: &[u64],
+    dir: Option<PathBuf>,
+    actions: &mut mpsc::UnboundedReceiver<ChannelAction>,
+    mut progress: impl FnMut(u64),
+) -> State {
+    let Some(dir) = dir else {
+        return State::Disconnected;
+    };
+    let mut copied = 0;
+    for ((path, name), size) in paths.iter().zip(names).zip(sizes) {
+        if matches!(actions.try_recv(), Ok(ChannelAction::CancelTransfer)) {
+            return State::Cancelled;
+        }
+        if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::copy(path, dir.join(name))) {
+            log::warn!("Failed to copy {}: {e}", path.display());
+            return State::Disconnected;
+        }
+        copied += size;
+        progress(copied);
+    }
+    State::Finished
+}
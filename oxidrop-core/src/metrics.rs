@@ -0,0 +1,73 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{TransferDirection, TransferState};
+
+/// How far back [`Metrics::throughput`] looks.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Totals since the instance was created, from
+/// [`Oxidrop::metrics`](crate::Oxidrop::metrics).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metrics {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// How many transfers ended in each final state.
+    pub transfers: HashMap<TransferState, u64>,
+    /// Bytes per second moved in either direction over the last few seconds.
+    pub throughput: u64,
+    /// Transfers that are waiting, running or queued, but not pending
+    /// consent.
+    pub active_transfers: usize,
+}
+
+/// Accumulates what [`Metrics`] reports.
+#[derive(Default)]
+pub(crate) struct Recorder {
+    bytes_sent: u64,
+    bytes_received: u64,
+    transfers: HashMap<TransferState, u64>,
+    /// Bytes moved recently and when, oldest first.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Recorder {
+    pub(crate) fn moved(&mut self, direction: TransferDirection, bytes: u64) {
+        match direction {
+            TransferDirection::Outbound => self.bytes_sent += bytes,
+            TransferDirection::Inbound => self.bytes_received += bytes,
+        }
+        let now = Instant::now();
+        self.prune(now);
+        self.samples.push_back((now, bytes));
+    }
+
+    pub(crate) fn ended(&mut self, state: TransferState) {
+        *self.transfers.entry(state).or_default() += 1;
+    }
+
+    pub(crate) fn snapshot(&mut self, active_transfers: usize) -> Metrics {
+        self.prune(Instant::now());
+        let recent: u64 = self.samples.iter().map(|(_, bytes)| bytes).sum();
+        Metrics {
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            transfers: self.transfers.clone(),
+            throughput: recent / THROUGHPUT_WINDOW.as_secs(),
+            active_transfers,
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > THROUGHPUT_WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+use tokio::{sync::broadcast, task::AbortHandle};
+
+use crate::{Endpoint, OxidropEvent, Progress, TransferDirection, TransferRequest, TransferState};
+
+/// Callbacks for [`OxidropEvent`]s, for applications that would rather not
+/// poll [`Oxidrop::events`](crate::Oxidrop::events). Every method does
+/// nothing by default.
+///
+/// Methods are called one at a time and in the order of the events, from a
+/// Tokio task. They shouldn't block; toolkits that need UI updates on their
+/// own thread should pass them on from here. Observers registered with
+/// [`Oxidrop::register_blocking_observer`](crate::Oxidrop::register_blocking_observer)
+/// are called from a thread of their own instead.
+#[allow(unused_variables)]
+pub trait TransferObserver: Send + 'static {
+    /// Called with every event, before the method specific to it if any.
+    fn event(&mut self, event: &OxidropEvent) {}
+
+    /// Only called while something is discovering endpoints.
+    fn endpoint_discovered(&mut self, endpoint: &Endpoint) {}
+
+    /// Called when a known endpoint changes its name or address.
+    fn endpoint_updated(&mut self, endpoint: &Endpoint) {}
+
+    fn endpoint_lost(&mut self, endpoint: &Endpoint) {}
+
+    fn transfer_requested(&mut self, request: &TransferRequest) {}
+
+    fn transfer_progress(&mut self, id: &str, direction: TransferDirection, progress: Progress) {}
+
+    fn transfer_state_changed(
+        &mut self,
+        id: &str,
+        direction: TransferDirection,
+        state: TransferState,
+    ) {
+    }
+
+    fn transfer_finished(&mut self, id: &str, direction: TransferDirection) {}
+
+    fn transfer_failed(&mut self, id: &str, direction: TransferDirection) {}
+}
+
+/// Keeps a [`TransferObserver`] registered until
+/// [`unregister`](Self::unregister) is called or the [`Oxidrop`](crate::Oxidrop)
+/// is dropped. Dropping the handle leaves the observer registered.
+#[derive(Debug)]
+pub struct ObserverHandle(Registration);
+
+#[derive(Debug)]
+enum Registration {
+    Task(AbortHandle),
+    /// Checked by the thread before each call.
+    Thread(Arc<AtomicBool>),
+}
+
+impl ObserverHandle {
+    pub fn unregister(self) {
+        match self.0 {
+            Registration::Task(task) => task.abort(),
+            Registration::Thread(stopped) => stopped.store(true, Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) fn register(
+    mut event_recv: broadcast::Receiver<OxidropEvent>,
+    mut observer: impl TransferObserver,
+) -> ObserverHandle {
+    let task = tokio::spawn(async move {
+        loop {
+            match event_recv.recv().await {
+                Ok(event) => dispatch(&mut observer, &event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Observer fell behind, skipping {n} events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    });
+    ObserverHandle(Registration::Task(task.abort_handle()))
+}
+
+/// Like [`register`], but calls `observer` from a thread of its own rather
+/// than a task, so it may block on the runtime.
+pub(crate) fn register_on_thread(
+    mut event_recv: broadcast::Receiver<OxidropEvent>,
+    mut observer: impl TransferObserver,
+) -> ObserverHandle {
+    let stopped = Arc::new(AtomicBool::new(false));
+    let thread_stopped = stopped.clone();
+    let spawned = thread::Builder::new()
+        .name("oxidrop-observer".to_string())
+        .spawn(move || {
+            loop {
+                let event = event_recv.blocking_recv();
+                if thread_stopped.load(Ordering::Relaxed) {
+                    return;
+                }
+                match event {
+                    Ok(event) => dispatch(&mut observer, &event),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        log::warn!("Observer fell behind, skipping {n} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        log::warn!("Failed to start observer thread: {e}");
+    }
+    ObserverHandle(Registration::Thread(stopped))
+}
+
+fn dispatch(observer: &mut impl TransferObserver, event: &OxidropEvent) {
+    observer.event(event);
+    match event {
+        OxidropEvent::EndpointDiscovered(endpoint) => observer.endpoint_discovered(endpoint),
+        OxidropEvent::EndpointUpdated(endpoint) => observer.endpoint_updated(endpoint),
+        OxidropEvent::EndpointLost(endpoint) => observer.endpoint_lost(endpoint),
+        OxidropEvent::TransferRequested(request) => observer.transfer_requested(request),
+        OxidropEvent::TransferProgress {
+            id,
+            direction,
+            progress,
+        } => observer.transfer_progress(id, *direction, *progress),
+        OxidropEvent::TransferStateChanged {
+            id,
+            direction,
+            state,
+        } => observer.transfer_state_changed(id, *direction, *state),
+        OxidropEvent::TransferFinished { id, direction } => {
+            observer.transfer_finished(id, *direction)
+        }
+        OxidropEvent::TransferFailed { id, direction } => observer.transfer_failed(id, *direction),
+        _ => {}
+    }
+}
@@ -0,0 +1,49 @@
+//! Logs everything the protocol implementation reports, for diagnosing interop
+//! problems from user reports. Enabled by the `proto-debug` feature and
+//! written to the `oxidrop::proto` log target.
+//!
+//! The frames themselves are decoded inside `rqs_lib`, so what's logged is
+//! the decoded result: every message on the bus, including the handshake
+//! states each connection goes through. File names and text payloads are
+//! redacted, leaving only their sizes.
+
+use rqs_lib::{SendInfo, channel::ChannelMessage, hdl::info::TransferMetadata};
+
+const TARGET: &str = "oxidrop::proto";
+
+pub(crate) fn log_message(msg: &ChannelMessage) {
+    log::debug!(
+        target: TARGET,
+        "{:?} id={} action={:?} type={:?} state={:?} meta={}",
+        msg.direction,
+        msg.id,
+        msg.action,
+        msg.rtype,
+        msg.state,
+        msg.meta.as_ref().map_or("none".to_string(), redacted),
+    );
+}
+
+pub(crate) fn log_send(info: &SendInfo) {
+    log::debug!(
+        target: TARGET,
+        "Send request id={} addr={}",
+        info.id,
+        info.addr,
+    );
+}
+
+fn redacted(meta: &TransferMetadata) -> String {
+    let files = meta.files.as_ref().map(Vec::len);
+    let text = meta.text_description.as_ref().map(String::len);
+    format!(
+        "{{ source: {:?}, files: {files:?}, text_bytes: {text:?}, text_type: {:?}, pin: {:?}, bytes: {}/{} }}",
+        meta.source
+            .as_ref()
+            .map(|s| (s.name.as_str(), s.device_type)),
+        meta.text_type,
+        meta.pin_code,
+        meta.ack_bytes,
+        meta.total_bytes,
+    )
+}
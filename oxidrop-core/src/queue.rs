@@ -0,0 +1,177 @@
+use std::collections::{HashSet, VecDeque};
+
+use rqs_lib::SendInfo;
+
+/// Outbound transfers waiting for one of a limited number of slots, in the
+/// order they'll start.
+#[derive(Default)]
+pub(crate) struct SendQueue {
+    running: HashSet<String>,
+    waiting: VecDeque<Waiting>,
+}
+
+pub(crate) struct Waiting {
+    pub(crate) info: SendInfo,
+    pub(crate) priority: i32,
+}
+
+impl SendQueue {
+    /// Queues `info` behind every waiting transfer of at least its
+    /// `priority`, returning it if it can start right away.
+    pub(crate) fn push(
+        &mut self,
+        info: SendInfo,
+        priority: i32,
+        limit: Option<usize>,
+    ) -> Option<SendInfo> {
+        if limit.is_some_and(|limit| self.running.len() >= limit) {
+            let index = self
+                .waiting
+                .iter()
+                .position(|w| w.priority < priority)
+                .unwrap_or(self.waiting.len());
+            self.waiting.insert(index, Waiting { info, priority });
+            return None;
+        }
+        self.running.insert(info.id.clone());
+        Some(info)
+    }
+
+    /// Frees the slot of transfer `id`, returning the transfers to start in
+    /// its place.
+    pub(crate) fn finish(&mut self, id: &str, limit: Option<usize>) -> Vec<SendInfo> {
+        if !self.running.remove(id) {
+            return Vec::new();
+        }
+        self.start_ready(limit)
+    }
+
+    /// Gives waiting transfers slots until `limit` are running, returning
+    /// the ones to start.
+    pub(crate) fn start_ready(&mut self, limit: Option<usize>) -> Vec<SendInfo> {
+        let mut ready = Vec::new();
+        while limit.is_none_or(|limit| self.running.len() < limit)
+            && let Some(next) = self.waiting.pop_front()
+        {
+            self.running.insert(next.info.id.clone());
+            ready.push(next.info);
+        }
+        ready
+    }
+
+    pub(crate) fn position(&self, id: &str) -> Option<usize> {
+        self.waiting.iter().position(|w| w.info.id == id)
+    }
+
+    pub(crate) fn waiting(&self) -> impl Iterator<Item = &Waiting> {
+        self.waiting.iter()
+    }
+
+    /// Moves waiting transfer `id` to `position`, or to the back if that's
+    /// past the end. Its priority is kept for placing later sends. Returns
+    /// whether `id` was waiting.
+    pub(crate) fn move_to(&mut self, id: &str, position: usize) -> bool {
+        let Some(waiting) = self.remove(id) else {
+            return false;
+        };
+        self.waiting
+            .insert(position.min(self.waiting.len()), waiting);
+        true
+    }
+
+    pub(crate) fn remove(&mut self, id: &str) -> Option<Waiting> {
+        let index = self.position(id)?;
+        self.waiting.remove(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rqs_lib::OutboundPayload;
+
+    use super::*;
+
+    fn send(id: &str) -> SendInfo {
+        SendInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            addr: "127.0.0.1:9300".to_string(),
+            ob: OutboundPayload::Files(Vec::new()),
+        }
+    }
+
+    fn waiting_ids(queue: &SendQueue) -> Vec<&str> {
+        queue.waiting().map(|w| w.info.id.as_str()).collect()
+    }
+
+    fn ids(sends: Vec<SendInfo>) -> Vec<String> {
+        sends.into_iter().map(|i| i.id).collect()
+    }
+
+    #[test]
+    fn starts_right_away_without_limit() {
+        let mut queue = SendQueue::default();
+        assert!(queue.push(send("a"), 0, None).is_some());
+        assert!(queue.push(send("b"), 0, None).is_some());
+        assert!(waiting_ids(&queue).is_empty());
+    }
+
+    #[test]
+    fn queues_by_priority_then_arrival() {
+        let mut queue = SendQueue::default();
+        assert!(queue.push(send("running"), 0, Some(1)).is_some());
+        assert!(queue.push(send("low"), -1, Some(1)).is_none());
+        assert!(queue.push(send("normal"), 0, Some(1)).is_none());
+        assert!(queue.push(send("high"), 5, Some(1)).is_none());
+        assert!(queue.push(send("normal2"), 0, Some(1)).is_none());
+        assert_eq!(waiting_ids(&queue), ["high", "normal", "normal2", "low"]);
+        assert_eq!(queue.position("normal2"), Some(2));
+    }
+
+    #[test]
+    fn finishing_starts_the_next() {
+        let mut queue = SendQueue::default();
+        queue.push(send("a"), 0, Some(1));
+        queue.push(send("b"), 0, Some(1));
+        assert!(queue.finish("unknown", Some(1)).is_empty());
+        assert_eq!(ids(queue.finish("a", Some(1))), ["b"]);
+        assert!(waiting_ids(&queue).is_empty());
+        assert!(queue.finish("b", Some(1)).is_empty());
+        assert!(queue.push(send("c"), 0, Some(1)).is_some());
+    }
+
+    #[test]
+    fn raising_the_limit_starts_as_many_as_fit() {
+        let mut queue = SendQueue::default();
+        for id in ["a", "b", "c", "d"] {
+            queue.push(send(id), 0, Some(1));
+        }
+        assert!(queue.start_ready(Some(1)).is_empty());
+        assert_eq!(ids(queue.start_ready(Some(3))), ["b", "c"]);
+        assert_eq!(waiting_ids(&queue), ["d"]);
+        assert_eq!(ids(queue.start_ready(None)), ["d"]);
+    }
+
+    #[test]
+    fn every_send_takes_a_slot() {
+        let mut queue = SendQueue::default();
+        assert!(queue.push(send("phone-0"), 0, Some(2)).is_some());
+        assert!(queue.push(send("phone-1"), 0, Some(2)).is_some());
+        assert!(queue.push(send("phone-2"), 0, Some(2)).is_none());
+    }
+
+    #[test]
+    fn moves_and_removes_waiting_sends() {
+        let mut queue = SendQueue::default();
+        queue.push(send("running"), 0, Some(1));
+        for id in ["a", "b", "c"] {
+            queue.push(send(id), 0, Some(1));
+        }
+        assert!(queue.move_to("c", 0));
+        assert!(queue.move_to("a", 10));
+        assert_eq!(waiting_ids(&queue), ["c", "b", "a"]);
+        assert!(queue.remove("b").is_some());
+        assert!(!queue.move_to("b", 0));
+        assert_eq!(waiting_ids(&queue), ["c", "a"]);
+    }
+}
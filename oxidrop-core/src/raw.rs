@@ -0,0 +1,46 @@
+//! Conversions between this crate's types and the underlying `rqs_lib` ones.
+//!
+//! These follow whatever `rqs_lib` version this crate depends on and may break
+//! in any release.
+
+use rqs_lib::{EndpointInfo, channel::ChannelMessage};
+
+use crate::{Endpoint, TransferRequest};
+
+pub use rqs_lib;
+
+impl From<EndpointInfo> for Endpoint {
+    fn from(info: EndpointInfo) -> Self {
+        Endpoint(info)
+    }
+}
+
+impl From<Endpoint> for EndpointInfo {
+    fn from(endpoint: Endpoint) -> Self {
+        endpoint.0
+    }
+}
+
+impl AsRef<EndpointInfo> for Endpoint {
+    fn as_ref(&self) -> &EndpointInfo {
+        &self.0
+    }
+}
+
+impl From<ChannelMessage> for TransferRequest {
+    fn from(msg: ChannelMessage) -> Self {
+        TransferRequest(msg)
+    }
+}
+
+impl From<TransferRequest> for ChannelMessage {
+    fn from(request: TransferRequest) -> Self {
+        request.0
+    }
+}
+
+impl AsRef<ChannelMessage> for TransferRequest {
+    fn as_ref(&self) -> &ChannelMessage {
+        &self.0
+    }
+}
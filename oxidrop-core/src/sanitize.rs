@@ -0,0 +1,112 @@
+//! Checks on the names senders give their files, which are used as paths in
+//! the download directory.
+
+/// What to do with received files whose names aren't safe to use on every
+/// platform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FileNamePolicy {
+    /// Leave names as the sender gave them.
+    Keep,
+    /// Rename files with unsafe names once they're received, and reject
+    /// transfers with names that would be written outside the download
+    /// directory.
+    #[default]
+    Sanitize,
+    /// Reject transfers offering any file with an unsafe name.
+    Reject,
+}
+
+const RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Whether saving a file named `name` could write outside the download
+/// directory. Such files can't be renamed after the fact, since they're
+/// already in the wrong place by then.
+pub(crate) fn escapes_dir(name: &str) -> bool {
+    name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\'])
+}
+
+/// A version of `name` that's safe to use as a file name everywhere, or
+/// `None` if it already is.
+pub(crate) fn sanitize(name: &str) -> Option<String> {
+    let mut safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || RESERVED_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, which can make names collide.
+    let trimmed = safe.trim_end_matches(['.', ' ']).len();
+    safe.truncate(trimmed);
+
+    let stem = safe.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|r| stem.eq_ignore_ascii_case(r)) {
+        safe.insert(0, '_');
+    }
+    if safe.is_empty() || safe.chars().all(|c| c == '.') {
+        safe = "_".to_string();
+    }
+    (safe != name).then_some(safe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_that_escape_the_download_dir() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "../etc/passwd",
+            "a/b",
+            "..\\windows",
+            "dir\\file",
+        ] {
+            assert!(escapes_dir(name), "{name:?}");
+        }
+        for name in ["...", "..hidden", "file.txt", ".profile"] {
+            assert!(!escapes_dir(name), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn leaves_safe_names_alone() {
+        for name in ["photo.jpg", "Résumé 2024.pdf", ".hidden", "日本語.txt"] {
+            assert_eq!(sanitize(name), None, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn replaces_reserved_and_control_characters() {
+        assert_eq!(sanitize("a<b>c:d.txt").as_deref(), Some("a_b_c_d.txt"));
+        assert_eq!(sanitize("what?*.txt").as_deref(), Some("what__.txt"));
+        assert_eq!(sanitize("tab\there").as_deref(), Some("tab_here"));
+        assert_eq!(sanitize("../up").as_deref(), Some(".._up"));
+    }
+
+    #[test]
+    fn prefixes_reserved_device_names() {
+        assert_eq!(sanitize("CON").as_deref(), Some("_CON"));
+        assert_eq!(sanitize("nul.txt").as_deref(), Some("_nul.txt"));
+        assert_eq!(sanitize("Com1.tar.gz").as_deref(), Some("_Com1.tar.gz"));
+        assert_eq!(sanitize("CONSOLE.txt"), None);
+    }
+
+    #[test]
+    fn trims_trailing_dots_and_spaces() {
+        assert_eq!(sanitize("notes.txt. ").as_deref(), Some("notes.txt"));
+        assert_eq!(sanitize("...").as_deref(), Some("_"));
+        assert_eq!(sanitize("..").as_deref(), Some("_"));
+    }
+}
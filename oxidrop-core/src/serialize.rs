@@ -0,0 +1,112 @@
+//! `serde` support for the types that wrap `rqs_lib`'s, which go through
+//! plain representations of what they expose.
+
+use rqs_lib::{
+    EndpointInfo, State, TextPayloadType,
+    channel::{ChannelDirection, ChannelMessage, TransferType},
+    hdl::info::{RemoteDeviceInfo, TransferMetadata},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{DeviceType, Endpoint, Payload, TransferRequest};
+
+#[derive(Serialize, Deserialize)]
+struct EndpointRepr {
+    id: String,
+    name: Option<String>,
+    fullname: String,
+    ip: Option<String>,
+    port: Option<String>,
+    device_type: DeviceType,
+}
+
+impl Serialize for Endpoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EndpointRepr {
+            id: self.0.id.clone(),
+            name: self.0.name.clone(),
+            fullname: self.0.fullname.clone(),
+            ip: self.0.ip.clone(),
+            port: self.0.port.clone(),
+            device_type: self.device_type(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Endpoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = EndpointRepr::deserialize(deserializer)?;
+        Ok(Endpoint(EndpointInfo {
+            fullname: repr.fullname,
+            id: repr.id,
+            name: repr.name,
+            ip: repr.ip,
+            port: repr.port,
+            rtype: Some(match repr.device_type {
+                DeviceType::Unknown => rqs_lib::DeviceType::Unknown,
+                DeviceType::Phone => rqs_lib::DeviceType::Phone,
+                DeviceType::Tablet => rqs_lib::DeviceType::Tablet,
+                DeviceType::Laptop => rqs_lib::DeviceType::Laptop,
+            }),
+            present: None,
+        }))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransferRequestRepr {
+    id: String,
+    sender_name: String,
+    payload: Payload,
+    total_bytes: u64,
+    pin_code: Option<String>,
+}
+
+impl Serialize for TransferRequest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TransferRequestRepr {
+            id: self.id().to_string(),
+            sender_name: self.sender_name().to_string(),
+            payload: self.payload(),
+            total_bytes: self.total_bytes(),
+            pin_code: self.pin_code().map(str::to_string),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserialized requests can be answered like the ones they were made from,
+/// since requests are told apart by id.
+impl<'de> Deserialize<'de> for TransferRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = TransferRequestRepr::deserialize(deserializer)?;
+        let (files, text, text_type) = match repr.payload {
+            Payload::Files(files) => (Some(files), None, None),
+            Payload::Text(text) => (None, Some(text), Some(TextPayloadType::Text)),
+            Payload::Url(text) => (None, Some(text), Some(TextPayloadType::Url)),
+            Payload::Wifi(text) => (None, Some(text), Some(TextPayloadType::Wifi)),
+        };
+        Ok(TransferRequest(ChannelMessage {
+            id: repr.id.clone(),
+            direction: ChannelDirection::LibToFront,
+            action: None,
+            rtype: Some(TransferType::Inbound),
+            state: Some(State::WaitingForUserConsent),
+            meta: Some(TransferMetadata {
+                id: repr.id,
+                destination: None,
+                source: Some(RemoteDeviceInfo {
+                    name: repr.sender_name,
+                    device_type: rqs_lib::DeviceType::Unknown,
+                }),
+                files,
+                pin_code: repr.pin_code,
+                text_description: text,
+                text_type,
+                total_bytes: repr.total_bytes,
+                ack_bytes: 0,
+            }),
+        }))
+    }
+}
@@ -0,0 +1,29 @@
+use std::path::Path;
+
+/// What to do with inbound requests that are larger than the free space in
+/// the download directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SpacePolicy {
+    /// Don't check.
+    Ignore,
+    /// Emit [`OxidropEvent::InsufficientSpace`](crate::OxidropEvent::InsufficientSpace)
+    /// and ask as usual.
+    #[default]
+    Warn,
+    /// Emit [`OxidropEvent::InsufficientSpace`](crate::OxidropEvent::InsufficientSpace)
+    /// and reject the request without asking.
+    Reject,
+}
+
+/// Bytes available to this user on the file system holding `dir`, if that
+/// can be told.
+#[cfg(unix)]
+pub(crate) fn available(dir: &Path) -> Option<u64> {
+    let stat = rustix::fs::statvfs(dir).ok()?;
+    Some(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn available(_dir: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,72 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{Error, File, Result};
+
+/// Numbers the instances in this process, so each stages in a directory of
+/// its own.
+static INSTANCES: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory for links that make `rqs_lib`, which sends every file
+/// under its own basename, send it under a different name. Removed on drop.
+pub(crate) struct Staging {
+    dir: PathBuf,
+    next: AtomicUsize,
+}
+
+impl Staging {
+    pub(crate) fn new() -> Self {
+        Staging {
+            dir: std::env::temp_dir().join(format!(
+                "oxidrop-{}-{}",
+                process::id(),
+                INSTANCES.fetch_add(1, Ordering::Relaxed)
+            )),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the path to hand to `rqs_lib` so that `file` arrives under its
+    /// [`File::wire_name`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(path = %file.path.display())))]
+    pub(crate) fn prepare(&self, file: &File) -> Result<PathBuf> {
+        let Some(name) = &file.display_name else {
+            return Ok(file.path.clone());
+        };
+        if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+            return Err(Error::InvalidFileName(name.clone()));
+        }
+        if file.path.file_name().is_some_and(|n| n == name.as_str()) {
+            return Ok(file.path.clone());
+        }
+
+        let dir = self
+            .dir
+            .join(self.next.fetch_add(1, Ordering::Relaxed).to_string());
+        let staged = dir.join(name);
+        fs::create_dir_all(&dir)
+            .and_then(|_| link(&file.path.canonicalize()?, &staged))
+            .map_err(|e| Error::Other(Box::new(e)))?;
+        Ok(staged)
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(unix)]
+fn link(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(not(unix))]
+fn link(original: &Path, link: &Path) -> io::Result<()> {
+    fs::hard_link(original, link).or_else(|_| fs::copy(original, link).map(|_| ()))
+}
@@ -0,0 +1,195 @@
+use rqs_lib::{
+    State, TextPayloadType,
+    channel::{ChannelDirection, ChannelMessage, TransferType},
+    hdl::info::TransferMetadata,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferDirection {
+    Inbound,
+    Outbound,
+}
+
+/// Where a transfer is in its lifecycle.
+///
+/// Transfers move from `Initiated` through `WaitingForConsent`, `Accepted`,
+/// `Transferring` and `Finalizing`, and end in exactly one of `Done`, `Rejected`, `Cancelled`,
+/// `Failed` or `TimedOut`. Steps may be skipped, but a transfer never leaves a final state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferState {
+    /// The devices are connecting and exchanging keys.
+    Initiated,
+    /// The receiver has been asked to accept the transfer.
+    WaitingForConsent,
+    /// The receiver accepted the transfer and no bytes have arrived yet. Only
+    /// inbound transfers accepted through this crate pass through this state.
+    Accepted,
+    /// Payload bytes are flowing.
+    Transferring,
+    /// All bytes have been transferred, but the transfer isn't complete yet.
+    Finalizing,
+    Done,
+    Rejected,
+    Cancelled,
+    /// The connection was lost before the transfer completed.
+    Failed,
+    /// The transfer was ended after waiting longer than
+    /// [`Config::consent_timeout`](crate::Config::consent_timeout) or
+    /// [`Config::stall_timeout`](crate::Config::stall_timeout) allows.
+    TimedOut,
+}
+
+/// Why an inbound request was rejected.
+///
+/// `rqs_lib` answers every rejection the same way, so senders aren't told
+/// which it was; it's recorded in [`OxidropEvent::TransferRejected`](crate::OxidropEvent::TransferRejected)
+/// and the history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RejectReason {
+    /// Another transfer is keeping this device busy.
+    Busy,
+    /// The download directory doesn't have room for the files.
+    NotEnoughSpace,
+    /// The transfer wasn't wanted.
+    #[default]
+    Declined,
+}
+
+impl TransferState {
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            TransferState::Done
+                | TransferState::Rejected
+                | TransferState::Cancelled
+                | TransferState::Failed
+                | TransferState::TimedOut
+        )
+    }
+
+    pub(crate) fn from_message(msg: &ChannelMessage) -> Option<(TransferDirection, Self)> {
+        if !matches!(msg.direction, ChannelDirection::LibToFront) {
+            return None;
+        }
+
+        let direction = match msg.rtype.as_ref()? {
+            TransferType::Inbound => TransferDirection::Inbound,
+            TransferType::Outbound => TransferDirection::Outbound,
+        };
+        let state = match msg.state.as_ref()? {
+            State::WaitingForUserConsent => TransferState::WaitingForConsent,
+            // A sender hears nothing more until the receiver answers.
+            State::SentIntroduction if direction == TransferDirection::Outbound => {
+                TransferState::WaitingForConsent
+            }
+            State::ReceivingFiles | State::SendingFiles => match &msg.meta {
+                Some(meta) if meta.total_bytes > 0 && meta.ack_bytes >= meta.total_bytes => {
+                    TransferState::Finalizing
+                }
+                _ => TransferState::Transferring,
+            },
+            State::Finished => TransferState::Done,
+            State::Rejected => TransferState::Rejected,
+            State::Cancelled => TransferState::Cancelled,
+            State::Disconnected => TransferState::Failed,
+            _ => TransferState::Initiated,
+        };
+
+        Some((direction, state))
+    }
+}
+
+/// How a transfer ended, as far as the sender is concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransferOutcome {
+    /// Everything arrived.
+    Finished,
+    /// The receiver rejected the transfer.
+    Declined,
+    /// Either side cancelled the transfer.
+    Cancelled,
+    /// The connection was lost before the transfer completed.
+    Disconnected,
+    /// The receiver didn't answer, or the transfer stalled, for too long.
+    TimedOut,
+}
+
+impl TransferOutcome {
+    /// The outcome a final state stands for, or `None` if `state` isn't final.
+    pub fn from_state(state: TransferState) -> Option<Self> {
+        match state {
+            TransferState::Done => Some(TransferOutcome::Finished),
+            TransferState::Rejected => Some(TransferOutcome::Declined),
+            TransferState::Cancelled => Some(TransferOutcome::Cancelled),
+            TransferState::Failed => Some(TransferOutcome::Disconnected),
+            TransferState::TimedOut => Some(TransferOutcome::TimedOut),
+            _ => None,
+        }
+    }
+}
+
+/// What a transfer carries.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Payload {
+    /// Names of the files offered.
+    Files(Vec<String>),
+    Text(String),
+    Url(String),
+    /// Credentials for a Wi-Fi network, described by its name.
+    Wifi(String),
+}
+
+impl Payload {
+    pub(crate) fn from_metadata(meta: &TransferMetadata) -> Self {
+        let Some(text) = meta.text_description.clone() else {
+            return Payload::Files(meta.files.clone().unwrap_or_default());
+        };
+        match meta.text_type {
+            Some(TextPayloadType::Url) => Payload::Url(text),
+            Some(TextPayloadType::Wifi) => Payload::Wifi(text),
+            Some(TextPayloadType::Text) | None => Payload::Text(text),
+        }
+    }
+}
+
+/// How much of a transfer's payload has been sent or received, in bytes.
+/// Sizes are 64-bit throughout, so transfers of files over 4 GiB report
+/// correctly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Progress {
+    pub transferred: u64,
+    pub total: u64,
+    /// Which of the transfer's files is being transferred, if that can be
+    /// told. `rqs_lib` only reports byte counts for the transfer as a whole,
+    /// so this is worked out from file sizes when sending and from the files
+    /// on disk when receiving.
+    pub file_index: Option<usize>,
+}
+
+impl Progress {
+    /// The fraction done, between 0 and 1.
+    pub fn fraction(self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.transferred as f64 / self.total as f64
+    }
+
+    pub(crate) fn from_message(msg: &ChannelMessage) -> Option<Self> {
+        if !matches!(msg.state, Some(State::ReceivingFiles | State::SendingFiles)) {
+            return None;
+        }
+        let meta = msg.meta.as_ref().filter(|m| m.total_bytes > 0)?;
+        Some(Progress {
+            transferred: meta.ack_bytes.min(meta.total_bytes),
+            total: meta.total_bytes,
+            file_index: None,
+        })
+    }
+}
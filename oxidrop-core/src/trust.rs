@@ -0,0 +1,90 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{Error, Result, TransferRequest};
+
+/// Names of devices the user has accepted transfers from before, kept in a
+/// file with one name per line.
+///
+/// Quick Share tells receivers neither the sender's device id nor its key
+/// before a transfer is accepted, so only names can be remembered. This is a
+/// name match, not a device identity: any device can rename itself to a
+/// remembered name, so don't accept transfers on the strength of it.
+pub struct TrustStore {
+    path: PathBuf,
+    devices: Mutex<BTreeSet<String>>,
+}
+
+impl TrustStore {
+    /// Reads the store at `path`, which doesn't need to exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let devices = match fs::read_to_string(&path) {
+            Ok(s) => s
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(Error::Other(Box::new(e))),
+        };
+        Ok(TrustStore {
+            path,
+            devices: Mutex::new(devices),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The remembered names, in alphabetical order.
+    pub fn devices(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.lock().contains(name)
+    }
+
+    /// Whether `request`'s sender goes by a remembered name.
+    pub fn trusts(&self, request: &TransferRequest) -> bool {
+        self.contains(request.sender_name())
+    }
+
+    pub fn add(&self, name: &str) -> Result<()> {
+        let mut devices = self.lock();
+        if devices.insert(name.to_string()) {
+            self.save(&devices)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether `name` was remembered.
+    pub fn remove(&self, name: &str) -> Result<bool> {
+        let mut devices = self.lock();
+        let removed = devices.remove(name);
+        if removed {
+            self.save(&devices)?;
+        }
+        Ok(removed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.devices.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn save(&self, devices: &BTreeSet<String>) -> Result<()> {
+        let contents: String = devices.iter().map(|d| format!("{d}\n")).collect();
+        self.path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, contents))
+            .map_err(|e| Error::Other(Box::new(e)))
+    }
+}
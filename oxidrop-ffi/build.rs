@@ -0,0 +1,26 @@
+use std::{env, path::PathBuf};
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    // The source tree may be read-only, e.g. when vendored, so the header
+    // goes to `OUT_DIR`. The `oxidrop-header` binary copies it to `include/`.
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("cbindgen.toml is valid");
+    // Only this crate's source is parsed; types from oxidrop-core are opaque
+    // to C anyway.
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/lib.rs"))
+        .generate();
+    match bindings {
+        Ok(bindings) => {
+            bindings.write_to_file(out_dir.join("oxidrop.h"));
+        }
+        // Keep building while the source doesn't parse, so rustc gets to
+        // report why.
+        Err(e) => println!("cargo::warning=Failed to generate oxidrop.h: {e}"),
+    }
+    println!("cargo::rerun-if-changed=src");
+    println!("cargo::rerun-if-changed=cbindgen.toml");
+}
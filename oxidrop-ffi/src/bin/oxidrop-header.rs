@@ -0,0 +1,27 @@
+//! Copies the `oxidrop.h` generated by the build script to `include/`, or to
+//! the path given as the only argument.
+
+use std::{env, fs, path::PathBuf, process::ExitCode};
+
+const HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/oxidrop.h"));
+
+fn main() -> ExitCode {
+    let path = env::args_os().nth(1).map_or_else(
+        || PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include/oxidrop.h"),
+        PathBuf::from,
+    );
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, HEADER));
+    match written {
+        Ok(()) => {
+            println!("Wrote {}", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {e}", path.display());
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,721 @@
+//! A C interface to oxidrop, declared in `oxidrop.h`, which is generated
+//! into `OUT_DIR` when the crate is built. `cargo run -p oxidrop-ffi --bin
+//! oxidrop-header` copies it to `include/oxidrop.h`.
+//!
+//! Strings going in are NUL-terminated UTF-8. Strings coming out belong to
+//! the caller, who frees them with [`oxidrop_string_free`]. Endpoints and
+//! requests handed to callbacks are only valid during the call; clone them
+//! to keep them.
+
+#[cfg(feature = "uniffi")]
+mod uniffi_api;
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_void},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+    sync::Mutex,
+};
+
+use oxidrop::{
+    Config, Endpoint, Error, File, ObserverHandle, Oxidrop, Progress, TransferDirection,
+    TransferHandle, TransferObserver, TransferRequest, TransferState, Visibility,
+};
+use tokio::{runtime::Runtime, task::AbortHandle};
+use tokio_stream::StreamExt;
+
+/// A running instance, with the runtime it runs on.
+pub struct OxidropInstance {
+    runtime: Runtime,
+    oxidrop: Oxidrop,
+    observer: Mutex<Option<ObserverHandle>>,
+    /// The task keeping discovery running, while it is.
+    discovery: Mutex<Option<AbortHandle>>,
+}
+
+pub struct OxidropEndpoint(Endpoint);
+
+pub struct OxidropRequest(TransferRequest);
+
+/// An outbound transfer started by [`oxidrop_send_files`].
+pub struct OxidropTransfer(TransferHandle);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OxidropStatus {
+    Ok,
+    /// A pointer was null or a string wasn't UTF-8.
+    InvalidArgument,
+    EndpointUnreachable,
+    ReceivingDisabled,
+    UnknownTransfer,
+    /// Anything else; see [`oxidrop_last_error`].
+    Failed,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum OxidropDirection {
+    Inbound,
+    Outbound,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum OxidropState {
+    Initiated,
+    WaitingForConsent,
+    Accepted,
+    Transferring,
+    Finalizing,
+    Done,
+    Rejected,
+    Cancelled,
+    Failed,
+    TimedOut,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum OxidropVisibility {
+    Visible,
+    Invisible,
+    Temporarily,
+}
+
+/// Functions called as things happen, each optional. They're called one at
+/// a time from a background thread, with `user_data` as first argument.
+/// They may call back into the library, including to free the instance.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct OxidropCallbacks {
+    pub user_data: *mut c_void,
+    /// Only called while discovery runs; see [`oxidrop_start_discovery`].
+    pub endpoint_discovered: Option<extern "C" fn(*mut c_void, *const OxidropEndpoint)>,
+    pub endpoint_lost: Option<extern "C" fn(*mut c_void, *const OxidropEndpoint)>,
+    pub transfer_requested: Option<extern "C" fn(*mut c_void, *const OxidropRequest)>,
+    /// Called with the transfer id, and bytes transferred out of the total.
+    pub transfer_progress:
+        Option<extern "C" fn(*mut c_void, *const c_char, OxidropDirection, u64, u64)>,
+    /// Called with the transfer id and its new state.
+    pub transfer_state_changed:
+        Option<extern "C" fn(*mut c_void, *const c_char, OxidropDirection, OxidropState)>,
+}
+
+/// The callbacks are only called from one thread at a time, and whoever
+/// registers them is responsible for `user_data` being usable from there.
+unsafe impl Send for OxidropCallbacks {}
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+impl From<TransferDirection> for OxidropDirection {
+    fn from(direction: TransferDirection) -> Self {
+        match direction {
+            TransferDirection::Inbound => OxidropDirection::Inbound,
+            TransferDirection::Outbound => OxidropDirection::Outbound,
+        }
+    }
+}
+
+impl From<TransferState> for OxidropState {
+    fn from(state: TransferState) -> Self {
+        match state {
+            TransferState::Initiated => OxidropState::Initiated,
+            TransferState::WaitingForConsent => OxidropState::WaitingForConsent,
+            TransferState::Accepted => OxidropState::Accepted,
+            TransferState::Transferring => OxidropState::Transferring,
+            TransferState::Finalizing => OxidropState::Finalizing,
+            TransferState::Done => OxidropState::Done,
+            TransferState::Rejected => OxidropState::Rejected,
+            TransferState::Cancelled => OxidropState::Cancelled,
+            TransferState::Failed => OxidropState::Failed,
+            TransferState::TimedOut => OxidropState::TimedOut,
+        }
+    }
+}
+
+impl TransferObserver for OxidropCallbacks {
+    fn endpoint_discovered(&mut self, endpoint: &Endpoint) {
+        if let Some(f) = self.endpoint_discovered {
+            f(self.user_data, &OxidropEndpoint(endpoint.clone()));
+        }
+    }
+
+    fn endpoint_lost(&mut self, endpoint: &Endpoint) {
+        if let Some(f) = self.endpoint_lost {
+            f(self.user_data, &OxidropEndpoint(endpoint.clone()));
+        }
+    }
+
+    fn transfer_requested(&mut self, request: &TransferRequest) {
+        if let Some(f) = self.transfer_requested {
+            f(self.user_data, &OxidropRequest(request.clone()));
+        }
+    }
+
+    fn transfer_progress(&mut self, id: &str, direction: TransferDirection, progress: Progress) {
+        if let Some(f) = self.transfer_progress
+            && let Ok(id) = CString::new(id)
+        {
+            f(
+                self.user_data,
+                id.as_ptr(),
+                direction.into(),
+                progress.transferred,
+                progress.total,
+            );
+        }
+    }
+
+    fn transfer_state_changed(
+        &mut self,
+        id: &str,
+        direction: TransferDirection,
+        state: TransferState,
+    ) {
+        if let Some(f) = self.transfer_state_changed
+            && let Ok(id) = CString::new(id)
+        {
+            f(self.user_data, id.as_ptr(), direction.into(), state.into());
+        }
+    }
+}
+
+fn fail(e: Error) -> OxidropStatus {
+    let status = match e {
+        Error::EndpointUnreachable(_) => OxidropStatus::EndpointUnreachable,
+        Error::ReceivingDisabled => OxidropStatus::ReceivingDisabled,
+        Error::UnknownTransfer(_) => OxidropStatus::UnknownTransfer,
+        Error::InvalidFileName(_) | Error::InvalidConfig(_) => OxidropStatus::InvalidArgument,
+        _ => OxidropStatus::Failed,
+    };
+    set_last_error(&e.to_string());
+    status
+}
+
+/// Runs the body of an entry point, turning a panic into `on_panic` so it
+/// doesn't unwind into the caller.
+fn guard<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|_| {
+        set_last_error("oxidrop panicked");
+        on_panic
+    })
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn status(result: oxidrop::Result<()>) -> OxidropStatus {
+    result.map_or_else(fail, |()| OxidropStatus::Ok)
+}
+
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+fn string_out(s: &str) -> *mut c_char {
+    CString::new(s.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// The message of the last error on this thread, or null. The string stays
+/// valid until the next call on this thread fails.
+#[unsafe(no_mangle)]
+pub extern "C" fn oxidrop_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+    })
+}
+
+/// # Safety
+///
+/// `s` must be null or a string returned by this library, not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_string_free(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            drop(unsafe { CString::from_raw(s) });
+        }
+    })
+}
+
+/// Starts an instance, or returns null on failure. `download_dir` may be
+/// null for the default, and `port` 0 for any port.
+///
+/// # Safety
+///
+/// `download_dir` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_new(
+    download_dir: *const c_char,
+    port: u16,
+) -> *mut OxidropInstance {
+    guard(ptr::null_mut(), || {
+        let download_dir = unsafe { str_arg(download_dir) }.map(PathBuf::from);
+        let config = Config {
+            port: (port != 0).then_some(port),
+            download_dir,
+            ..Default::default()
+        };
+        let runtime = match tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                set_last_error(&e.to_string());
+                return ptr::null_mut();
+            }
+        };
+        match runtime.block_on(Oxidrop::new(config)) {
+            Ok(oxidrop) => Box::into_raw(Box::new(OxidropInstance {
+                runtime,
+                oxidrop,
+                observer: Mutex::new(None),
+                discovery: Mutex::new(None),
+            })),
+            Err(e) => {
+                fail(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Stops the instance, dropping transfers that are still running.
+///
+/// # Safety
+///
+/// `instance` must be null or returned by [`oxidrop_new`], not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_free(instance: *mut OxidropInstance) {
+    guard((), || {
+        if instance.is_null() {
+            return;
+        }
+        let OxidropInstance {
+            runtime,
+            oxidrop,
+            observer,
+            discovery,
+        } = *unsafe { Box::from_raw(instance) };
+        {
+            let _guard = runtime.enter();
+            drop((oxidrop, observer, discovery));
+        }
+        // Unlike dropping it, this is allowed from within the runtime.
+        runtime.shutdown_background();
+    })
+}
+
+/// Replaces the callbacks called as things happen.
+///
+/// # Safety
+///
+/// `instance` must be a live instance.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_set_callbacks(
+    instance: *const OxidropInstance,
+    callbacks: OxidropCallbacks,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let Some(instance) = (unsafe { instance.as_ref() }) else {
+            return OxidropStatus::InvalidArgument;
+        };
+        let handle = instance.oxidrop.register_blocking_observer(callbacks);
+        let previous = instance
+            .observer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(handle);
+        if let Some(previous) = previous {
+            previous.unregister();
+        }
+        OxidropStatus::Ok
+    })
+}
+
+/// # Safety
+///
+/// `instance` must be a live instance.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_set_visibility(
+    instance: *const OxidropInstance,
+    visibility: OxidropVisibility,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let Some(instance) = (unsafe { instance.as_ref() }) else {
+            return OxidropStatus::InvalidArgument;
+        };
+        let visibility = match visibility {
+            OxidropVisibility::Visible => Visibility::Visible,
+            OxidropVisibility::Invisible => Visibility::Invisible,
+            OxidropVisibility::Temporarily => Visibility::Temporarily,
+        };
+        status(
+            instance
+                .runtime
+                .block_on(instance.oxidrop.set_visibility(visibility)),
+        )
+    })
+}
+
+/// Looks for nearby devices, reporting them to the `endpoint_discovered`
+/// callback, until [`oxidrop_stop_discovery`] is called.
+///
+/// # Safety
+///
+/// `instance` must be a live instance.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_start_discovery(
+    instance: *const OxidropInstance,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let Some(instance) = (unsafe { instance.as_ref() }) else {
+            return OxidropStatus::InvalidArgument;
+        };
+        let mut endpoints = match instance
+            .runtime
+            .block_on(instance.oxidrop.discover_endpoints())
+        {
+            Ok(endpoints) => endpoints,
+            Err(e) => return fail(e),
+        };
+        // Endpoints reach the callbacks through the event bus, as long as the
+        // stream keeps discovery running.
+        let task = instance
+            .runtime
+            .spawn(async move { while endpoints.next().await.is_some() {} });
+        let previous = instance
+            .discovery
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(task.abort_handle());
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+        OxidropStatus::Ok
+    })
+}
+
+/// # Safety
+///
+/// `instance` must be a live instance.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_stop_discovery(instance: *const OxidropInstance) {
+    guard((), || {
+        let Some(instance) = (unsafe { instance.as_ref() }) else {
+            return;
+        };
+        let task = instance
+            .discovery
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(task) = task {
+            task.abort();
+        }
+    })
+}
+
+/// Sends `count` files to `endpoint`. On success, `*transfer` is set to the
+/// new transfer, to free with [`oxidrop_transfer_free`].
+///
+/// # Safety
+///
+/// `instance` must be a live instance, `endpoint` a live endpoint, `paths`
+/// an array of `count` NUL-terminated strings, and `transfer` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_send_files(
+    instance: *const OxidropInstance,
+    endpoint: *const OxidropEndpoint,
+    paths: *const *const c_char,
+    count: usize,
+    transfer: *mut *mut OxidropTransfer,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let (Some(instance), Some(endpoint)) =
+            (unsafe { instance.as_ref() }, unsafe { endpoint.as_ref() })
+        else {
+            return OxidropStatus::InvalidArgument;
+        };
+        if paths.is_null() || transfer.is_null() {
+            return OxidropStatus::InvalidArgument;
+        }
+        let paths = unsafe { std::slice::from_raw_parts(paths, count) };
+        let Some(files) = paths
+            .iter()
+            .map(|&p| unsafe { str_arg(p) }.map(File::new))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return OxidropStatus::InvalidArgument;
+        };
+
+        match instance
+            .runtime
+            .block_on(instance.oxidrop.send_files(&endpoint.0, files.into_iter()))
+        {
+            Ok(handle) => {
+                unsafe { *transfer = Box::into_raw(Box::new(OxidropTransfer(handle))) };
+                OxidropStatus::Ok
+            }
+            Err(e) => fail(e),
+        }
+    })
+}
+
+/// # Safety
+///
+/// `instance` must be a live instance and `transfer` a live transfer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_cancel_transfer(
+    instance: *const OxidropInstance,
+    transfer: *const OxidropTransfer,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let (Some(instance), Some(transfer)) =
+            (unsafe { instance.as_ref() }, unsafe { transfer.as_ref() })
+        else {
+            return OxidropStatus::InvalidArgument;
+        };
+        status(
+            instance
+                .runtime
+                .block_on(instance.oxidrop.cancel_transfer(&transfer.0))
+                .map(|_| ()),
+        )
+    })
+}
+
+/// # Safety
+///
+/// `instance` must be a live instance and `request` a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_accept(
+    instance: *const OxidropInstance,
+    request: *const OxidropRequest,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let (Some(instance), Some(request)) =
+            (unsafe { instance.as_ref() }, unsafe { request.as_ref() })
+        else {
+            return OxidropStatus::InvalidArgument;
+        };
+        status(
+            instance
+                .runtime
+                .block_on(instance.oxidrop.accept_transfer(&request.0)),
+        )
+    })
+}
+
+/// # Safety
+///
+/// `instance` must be a live instance and `request` a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_reject(
+    instance: *const OxidropInstance,
+    request: *const OxidropRequest,
+) -> OxidropStatus {
+    guard(OxidropStatus::Failed, || {
+        let (Some(instance), Some(request)) =
+            (unsafe { instance.as_ref() }, unsafe { request.as_ref() })
+        else {
+            return OxidropStatus::InvalidArgument;
+        };
+        status(
+            instance
+                .runtime
+                .block_on(instance.oxidrop.reject_transfer(&request.0)),
+        )
+    })
+}
+
+/// # Safety
+///
+/// `endpoint` must be a live endpoint.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_endpoint_clone(
+    endpoint: *const OxidropEndpoint,
+) -> *mut OxidropEndpoint {
+    guard(ptr::null_mut(), || match unsafe { endpoint.as_ref() } {
+        Some(e) => Box::into_raw(Box::new(OxidropEndpoint(e.0.clone()))),
+        None => ptr::null_mut(),
+    })
+}
+
+/// # Safety
+///
+/// `endpoint` must be null or returned by [`oxidrop_endpoint_clone`], not
+/// freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_endpoint_free(endpoint: *mut OxidropEndpoint) {
+    guard((), || {
+        if !endpoint.is_null() {
+            drop(unsafe { Box::from_raw(endpoint) });
+        }
+    })
+}
+
+/// # Safety
+///
+/// `endpoint` must be a live endpoint.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_endpoint_id(endpoint: *const OxidropEndpoint) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { endpoint.as_ref() }.map_or(ptr::null_mut(), |e| string_out(e.0.id()))
+    })
+}
+
+/// # Safety
+///
+/// `endpoint` must be a live endpoint.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_endpoint_name(endpoint: *const OxidropEndpoint) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { endpoint.as_ref() }.map_or(ptr::null_mut(), |e| string_out(e.0.name()))
+    })
+}
+
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_clone(
+    request: *const OxidropRequest,
+) -> *mut OxidropRequest {
+    guard(ptr::null_mut(), || match unsafe { request.as_ref() } {
+        Some(r) => Box::into_raw(Box::new(OxidropRequest(r.0.clone()))),
+        None => ptr::null_mut(),
+    })
+}
+
+/// # Safety
+///
+/// `request` must be null or returned by [`oxidrop_request_clone`], not
+/// freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_free(request: *mut OxidropRequest) {
+    guard((), || {
+        if !request.is_null() {
+            drop(unsafe { Box::from_raw(request) });
+        }
+    })
+}
+
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_id(request: *const OxidropRequest) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { request.as_ref() }.map_or(ptr::null_mut(), |r| string_out(r.0.id()))
+    })
+}
+
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_sender_name(
+    request: *const OxidropRequest,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { request.as_ref() }.map_or(ptr::null_mut(), |r| string_out(r.0.sender_name()))
+    })
+}
+
+/// The code to compare with the one the sender shows, or null.
+///
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_pin_code(request: *const OxidropRequest) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { request.as_ref() }
+            .and_then(|r| r.0.pin_code())
+            .map_or(ptr::null_mut(), string_out)
+    })
+}
+
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_total_bytes(request: *const OxidropRequest) -> u64 {
+    guard(0, || {
+        unsafe { request.as_ref() }.map_or(0, |r| r.0.total_bytes())
+    })
+}
+
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_file_count(request: *const OxidropRequest) -> usize {
+    guard(0, || {
+        unsafe { request.as_ref() }.map_or(0, |r| r.0.files().len())
+    })
+}
+
+/// The name of the `index`th file offered, or null if there's no such file.
+///
+/// # Safety
+///
+/// `request` must be a live request.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_request_file_name(
+    request: *const OxidropRequest,
+    index: usize,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { request.as_ref() }
+            .and_then(|r| r.0.files().get(index))
+            .map_or(ptr::null_mut(), |f| string_out(f))
+    })
+}
+
+/// The id progress and state callbacks give for this transfer.
+///
+/// # Safety
+///
+/// `transfer` must be a live transfer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_transfer_id(transfer: *const OxidropTransfer) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        unsafe { transfer.as_ref() }.map_or(ptr::null_mut(), |t| string_out(t.0.id()))
+    })
+}
+
+/// # Safety
+///
+/// `transfer` must be null or returned by [`oxidrop_send_files`], not freed
+/// yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn oxidrop_transfer_free(transfer: *mut OxidropTransfer) {
+    guard((), || {
+        if !transfer.is_null() {
+            drop(unsafe { Box::from_raw(transfer) });
+        }
+    })
+}
@@ -0,0 +1,287 @@
+//! The API for UniFFI bindings, such as Kotlin and Swift ones. Generate them
+//! from the built library with `uniffi-bindgen generate --library`.
+//!
+//! Streams become calls on a [`ShareListener`] the application implements.
+//! Methods block until they're done, so call them off the UI thread.
+
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use oxidrop::{
+    Config, Endpoint, Error, File, ObserverHandle, Oxidrop, Progress, TransferDirection,
+    TransferHandle, TransferObserver, TransferRequest, TransferState, Visibility,
+};
+use tokio::{runtime::Runtime, task::AbortHandle};
+use tokio_stream::StreamExt;
+
+use crate::{OxidropDirection, OxidropState, OxidropVisibility};
+
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum ShareError {
+    InvalidArgument(String),
+    EndpointUnreachable(String),
+    ReceivingDisabled(String),
+    UnknownTransfer(String),
+    Failed(String),
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareError::InvalidArgument(m)
+            | ShareError::EndpointUnreachable(m)
+            | ShareError::ReceivingDisabled(m)
+            | ShareError::UnknownTransfer(m)
+            | ShareError::Failed(m) => f.write_str(m),
+        }
+    }
+}
+
+impl From<Error> for ShareError {
+    fn from(e: Error) -> Self {
+        let message = e.to_string();
+        match e {
+            Error::EndpointUnreachable(_) => ShareError::EndpointUnreachable(message),
+            Error::ReceivingDisabled => ShareError::ReceivingDisabled(message),
+            Error::UnknownTransfer(_) => ShareError::UnknownTransfer(message),
+            Error::InvalidFileName(_) | Error::InvalidConfig(_) => {
+                ShareError::InvalidArgument(message)
+            }
+            _ => ShareError::Failed(message),
+        }
+    }
+}
+
+/// A nearby device that can be sent to.
+#[derive(uniffi::Object)]
+pub struct ShareEndpoint(Endpoint);
+
+#[uniffi::export]
+impl ShareEndpoint {
+    pub fn id(&self) -> String {
+        self.0.id().to_string()
+    }
+
+    pub fn name(&self) -> String {
+        self.0.name().to_string()
+    }
+}
+
+/// A device asking to send something, to answer with
+/// [`QuickShare::accept`] or [`QuickShare::reject`].
+#[derive(uniffi::Object)]
+pub struct ShareRequest(TransferRequest);
+
+#[uniffi::export]
+impl ShareRequest {
+    pub fn id(&self) -> String {
+        self.0.id().to_string()
+    }
+
+    pub fn sender_name(&self) -> String {
+        self.0.sender_name().to_string()
+    }
+
+    pub fn files(&self) -> Vec<String> {
+        self.0.files().to_vec()
+    }
+
+    pub fn text(&self) -> Option<String> {
+        self.0.text().map(str::to_string)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.0.total_bytes()
+    }
+
+    pub fn pin_code(&self) -> Option<String> {
+        self.0.pin_code().map(str::to_string)
+    }
+}
+
+/// An outbound transfer started by [`QuickShare::send_files`].
+#[derive(uniffi::Object)]
+pub struct ShareTransfer(TransferHandle);
+
+#[uniffi::export]
+impl ShareTransfer {
+    /// The id listener calls give for this transfer.
+    pub fn id(&self) -> String {
+        self.0.id().to_string()
+    }
+}
+
+/// Told what happens, one call at a time from a background thread. Calls
+/// may go back into the [`QuickShare`], such as to accept a request.
+#[uniffi::export(with_foreign)]
+pub trait ShareListener: Send + Sync {
+    /// Only called while discovery runs; see [`QuickShare::start_discovery`].
+    fn endpoint_discovered(&self, endpoint: Arc<ShareEndpoint>);
+    fn endpoint_lost(&self, endpoint: Arc<ShareEndpoint>);
+    fn transfer_requested(&self, request: Arc<ShareRequest>);
+    fn transfer_progress(
+        &self,
+        id: String,
+        direction: OxidropDirection,
+        transferred: u64,
+        total: u64,
+    );
+    fn transfer_state_changed(&self, id: String, direction: OxidropDirection, state: OxidropState);
+}
+
+struct Listener(Arc<dyn ShareListener>);
+
+impl TransferObserver for Listener {
+    fn endpoint_discovered(&mut self, endpoint: &Endpoint) {
+        self.0
+            .endpoint_discovered(Arc::new(ShareEndpoint(endpoint.clone())));
+    }
+
+    fn endpoint_lost(&mut self, endpoint: &Endpoint) {
+        self.0
+            .endpoint_lost(Arc::new(ShareEndpoint(endpoint.clone())));
+    }
+
+    fn transfer_requested(&mut self, request: &TransferRequest) {
+        self.0
+            .transfer_requested(Arc::new(ShareRequest(request.clone())));
+    }
+
+    fn transfer_progress(&mut self, id: &str, direction: TransferDirection, progress: Progress) {
+        self.0.transfer_progress(
+            id.to_string(),
+            direction.into(),
+            progress.transferred,
+            progress.total,
+        );
+    }
+
+    fn transfer_state_changed(
+        &mut self,
+        id: &str,
+        direction: TransferDirection,
+        state: TransferState,
+    ) {
+        self.0
+            .transfer_state_changed(id.to_string(), direction.into(), state.into());
+    }
+}
+
+/// A running instance, with the runtime it runs on.
+#[derive(uniffi::Object)]
+pub struct QuickShare {
+    runtime: Runtime,
+    oxidrop: Oxidrop,
+    listener: Mutex<Option<ObserverHandle>>,
+    discovery: Mutex<Option<AbortHandle>>,
+}
+
+#[uniffi::export]
+impl QuickShare {
+    #[uniffi::constructor]
+    pub fn new(download_dir: Option<String>, port: Option<u16>) -> Result<Arc<Self>, ShareError> {
+        let config = Config {
+            port,
+            download_dir: download_dir.map(PathBuf::from),
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| ShareError::Failed(e.to_string()))?;
+        let oxidrop = runtime.block_on(Oxidrop::new(config))?;
+        Ok(Arc::new(QuickShare {
+            runtime,
+            oxidrop,
+            listener: Mutex::new(None),
+            discovery: Mutex::new(None),
+        }))
+    }
+
+    /// Replaces the listener told what happens.
+    pub fn set_listener(&self, listener: Arc<dyn ShareListener>) {
+        let handle = self.oxidrop.register_blocking_observer(Listener(listener));
+        let previous = self
+            .listener
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(handle);
+        if let Some(previous) = previous {
+            previous.unregister();
+        }
+    }
+
+    pub fn set_visibility(&self, visibility: OxidropVisibility) -> Result<(), ShareError> {
+        let visibility = match visibility {
+            OxidropVisibility::Visible => Visibility::Visible,
+            OxidropVisibility::Invisible => Visibility::Invisible,
+            OxidropVisibility::Temporarily => Visibility::Temporarily,
+        };
+        Ok(self
+            .runtime
+            .block_on(self.oxidrop.set_visibility(visibility))?)
+    }
+
+    /// Looks for nearby devices, reporting them to the listener, until
+    /// [`stop_discovery`](Self::stop_discovery) is called.
+    pub fn start_discovery(&self) -> Result<(), ShareError> {
+        let mut endpoints = self.runtime.block_on(self.oxidrop.discover_endpoints())?;
+        let task = self
+            .runtime
+            .spawn(async move { while endpoints.next().await.is_some() {} });
+        let previous = self
+            .discovery
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(task.abort_handle());
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    pub fn stop_discovery(&self) {
+        let task = self
+            .discovery
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(task) = task {
+            task.abort();
+        }
+    }
+
+    pub fn send_files(
+        &self,
+        endpoint: Arc<ShareEndpoint>,
+        paths: Vec<String>,
+    ) -> Result<Arc<ShareTransfer>, ShareError> {
+        let files = paths.into_iter().map(File::new);
+        let handle = self
+            .runtime
+            .block_on(self.oxidrop.send_files(&endpoint.0, files))?;
+        Ok(Arc::new(ShareTransfer(handle)))
+    }
+
+    pub fn cancel_transfer(&self, transfer: Arc<ShareTransfer>) -> Result<(), ShareError> {
+        self.runtime
+            .block_on(self.oxidrop.cancel_transfer(&transfer.0))?;
+        Ok(())
+    }
+
+    pub fn accept(&self, request: Arc<ShareRequest>) -> Result<(), ShareError> {
+        Ok(self
+            .runtime
+            .block_on(self.oxidrop.accept_transfer(&request.0))?)
+    }
+
+    pub fn reject(&self, request: Arc<ShareRequest>) -> Result<(), ShareError> {
+        Ok(self
+            .runtime
+            .block_on(self.oxidrop.reject_transfer(&request.0))?)
+    }
+}
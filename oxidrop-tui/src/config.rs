@@ -0,0 +1,240 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+use tokio_stream::{Stream, StreamExt, wrappers::IntervalStream};
+use toml_edit::DocumentMut;
+
+use crate::paths;
+
+/// Settings that can be given at the top level of the config file or in a
+/// `[profiles.<name>]` table, which overrides the top level.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub port: Option<u16>,
+    pub download_dir: Option<PathBuf>,
+    /// Whether `receive` shows this device to others, visible if unset.
+    pub visibility: Option<Visibility>,
+    /// Where the names of devices whose transfers were accepted are
+    /// remembered, the data directory's `trusted-devices` if unset.
+    pub trust_store: Option<PathBuf>,
+    /// Devices whose requests are rejected without asking.
+    pub blocked_senders: Option<Vec<String>>,
+    /// If set, only these devices are asked about.
+    pub allowed_senders: Option<Vec<String>>,
+    pub max_concurrent_sends: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Visible,
+    Invisible,
+    /// Visible for a while, then hidden again
+    Temporarily,
+}
+
+impl From<Visibility> for oxidrop::Visibility {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Visible => oxidrop::Visibility::Visible,
+            Visibility::Invisible => oxidrop::Visibility::Invisible,
+            Visibility::Temporarily => oxidrop::Visibility::Temporarily,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    #[serde(flatten)]
+    settings: Settings,
+    profiles: HashMap<String, Settings>,
+}
+
+impl Settings {
+    pub fn overridden_by(self, other: Settings) -> Settings {
+        Settings {
+            port: other.port.or(self.port),
+            download_dir: other.download_dir.or(self.download_dir),
+            visibility: other.visibility.or(self.visibility),
+            trust_store: other.trust_store.or(self.trust_store),
+            blocked_senders: other.blocked_senders.or(self.blocked_senders),
+            allowed_senders: other.allowed_senders.or(self.allowed_senders),
+            max_concurrent_sends: other.max_concurrent_sends.or(self.max_concurrent_sends),
+        }
+    }
+
+    /// The instance config these settings stand for, as `receive` runs it.
+    pub fn core_config(&self) -> anyhow::Result<oxidrop::Config> {
+        Ok(oxidrop::Config {
+            port: self.port,
+            download_dir: self.download_dir.clone(),
+            trust_store: Some(match &self.trust_store {
+                Some(path) => path.clone(),
+                None => paths::data_dir()?.join("trusted-devices"),
+            }),
+            visibility: self
+                .visibility
+                .map_or(oxidrop::Visibility::Visible, Into::into),
+            blocked_senders: self.blocked_senders.clone().unwrap_or_default(),
+            allowed_senders: self.allowed_senders.clone(),
+            max_concurrent_sends: self.max_concurrent_sends,
+            ..Default::default()
+        })
+    }
+}
+
+/// The keys `config get` and `config set` accept.
+pub const KEYS: &[&str] = &[
+    "port",
+    "download_dir",
+    "visibility",
+    "trust_store",
+    "max_concurrent_sends",
+];
+
+/// Reads the config file, if there is one, applying `profile` on top.
+pub fn load(profile: Option<&str>) -> anyhow::Result<Settings> {
+    let path = paths::config_file()?;
+    let file = match fs::read_to_string(&path) {
+        Ok(s) => {
+            toml::from_str(&s).with_context(|| format!("Failed to parse {}", path.display()))?
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => ConfigFile::default(),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display()))?,
+    };
+
+    match profile {
+        Some(name) => {
+            let profile = file
+                .profiles
+                .get(name)
+                .with_context(|| format!("Unknown profile {name:?}"))?;
+            Ok(file.settings.overridden_by(profile.clone()))
+        }
+        None => Ok(file.settings),
+    }
+}
+
+/// Loads the settings again whenever the config file changes, with
+/// `overrides` applied on top.
+pub fn watch(
+    profile: Option<String>,
+    overrides: Settings,
+) -> impl Stream<Item = anyhow::Result<Settings>> {
+    let mut last_modified = modified_time();
+    IntervalStream::new(tokio::time::interval(Duration::from_secs(2))).filter_map(move |_| {
+        let modified = modified_time();
+        if modified == last_modified {
+            return None;
+        }
+        last_modified = modified;
+        Some(load(profile.as_deref()).map(|s| s.overridden_by(overrides.clone())))
+    })
+}
+
+fn modified_time() -> Option<SystemTime> {
+    fs::metadata(paths::config_file().ok()?)
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// Returns the value of `key` after applying `profile`, if it's set.
+pub fn get(profile: Option<&str>, key: &str) -> anyhow::Result<Option<String>> {
+    let settings = load(profile)?;
+    Ok(match key {
+        "port" => settings.port.map(|p| p.to_string()),
+        "download_dir" => settings.download_dir.map(|d| d.display().to_string()),
+        "visibility" => settings
+            .visibility
+            .and_then(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_string()),
+        "trust_store" => settings.trust_store.map(|p| p.display().to_string()),
+        "max_concurrent_sends" => settings.max_concurrent_sends.map(|n| n.to_string()),
+        _ => bail!("Unknown key {key:?}, expected one of {}", KEYS.join(", ")),
+    })
+}
+
+/// Validates `value` and writes it to the config file, in the `profile` table
+/// if one is given. The rest of the file is left as it was.
+pub fn set(profile: Option<&str>, key: &str, value: &str) -> anyhow::Result<()> {
+    let item = match key {
+        "port" => {
+            let port: u16 = value
+                .parse()
+                .ok()
+                .filter(|&p| p != 0)
+                .with_context(|| format!("Invalid port {value:?}"))?;
+            toml_edit::value(i64::from(port))
+        }
+        "download_dir" => {
+            if !PathBuf::from(value).is_dir() {
+                bail!("{value:?} is not a directory");
+            }
+            toml_edit::value(value)
+        }
+        "visibility" => {
+            let visibility = Visibility::from_str(value, true)
+                .ok()
+                .and_then(|v| v.to_possible_value())
+                .with_context(|| format!("Invalid visibility {value:?}"))?;
+            toml_edit::value(visibility.get_name())
+        }
+        "trust_store" => {
+            if PathBuf::from(value).is_dir() {
+                bail!("{value:?} is a directory");
+            }
+            toml_edit::value(value)
+        }
+        "max_concurrent_sends" => {
+            let limit: usize = value
+                .parse()
+                .ok()
+                .filter(|&n| n != 0)
+                .with_context(|| format!("Invalid limit {value:?}"))?;
+            toml_edit::value(i64::try_from(limit).context("Limit too large")?)
+        }
+        _ => bail!("Unknown key {key:?}, expected one of {}", KEYS.join(", ")),
+    };
+
+    let path = paths::config_file()?;
+    let mut doc = match fs::read_to_string(&path) {
+        Ok(s) => s
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", path.display()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display()))?,
+    };
+
+    let table = match profile {
+        Some(name) => doc["profiles"]
+            .or_insert({
+                let mut profiles = toml_edit::Table::new();
+                profiles.set_implicit(true);
+                toml_edit::Item::Table(profiles)
+            })
+            .as_table_mut()
+            .context("profiles is not a table")?
+            .entry(name)
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .with_context(|| format!("Profile {name:?} is not a table"))?,
+        None => doc.as_table_mut(),
+    };
+    table[key] = item;
+
+    let contents = doc.to_string();
+    toml::from_str::<ConfigFile>(&contents).context("Refusing to write an invalid config")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,89 @@
+use std::{borrow::Cow, env};
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The decorative characters the UI draws, so they can be swapped for plain
+/// ASCII on terminals or fonts that can't show them.
+pub struct Glyphs {
+    pub up: &'static str,
+    pub down: &'static str,
+    pub collapsed: &'static str,
+    pub expanded: &'static str,
+    pub ellipsis: &'static str,
+}
+
+pub const UNICODE: Glyphs = Glyphs {
+    up: "￪",
+    down: "￬",
+    collapsed: "▸",
+    expanded: "▾",
+    ellipsis: "…",
+};
+
+pub const ASCII: Glyphs = Glyphs {
+    up: "Up",
+    down: "Down",
+    collapsed: "+",
+    expanded: "-",
+    ellipsis: "...",
+};
+
+impl Glyphs {
+    /// Shortens `text` to at most `width` terminal columns, ending it with an
+    /// ellipsis if anything was cut. Wide characters like CJK and emoji count
+    /// as two columns.
+    pub fn truncate<'a>(&self, text: &'a str, width: usize) -> Cow<'a, str> {
+        if text.width() <= width {
+            return Cow::Borrowed(text);
+        }
+
+        let ellipsis_width = self.ellipsis.width();
+        if width < ellipsis_width {
+            return Cow::Owned(".".repeat(width));
+        }
+        let mut budget = width - ellipsis_width;
+        let mut truncated = String::new();
+        for c in text.chars() {
+            let w = c.width().unwrap_or(0);
+            if w > budget {
+                break;
+            }
+            budget -= w;
+            truncated.push(c);
+        }
+        truncated.push_str(self.ellipsis);
+        Cow::Owned(truncated)
+    }
+}
+
+/// Picks [`ASCII`] if `force_ascii` is set or the environment suggests
+/// Unicode won't display properly, i.e. the locale isn't UTF-8 or the
+/// terminal is the Linux console.
+pub fn select(force_ascii: bool) -> &'static Glyphs {
+    if force_ascii || !unicode_supported() {
+        &ASCII
+    } else {
+        &UNICODE
+    }
+}
+
+fn unicode_supported() -> bool {
+    if matches!(env::var("TERM").as_deref(), Ok("linux" | "dumb")) {
+        return false;
+    }
+
+    // Same precedence as setlocale(3): the first one that's set and non-empty
+    // decides.
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        // Windows doesn't set these, and its terminals handle Unicode.
+        None => cfg!(windows),
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    fs::{self, File, TryLockError},
+    io::Write,
+};
+
+use anyhow::{Context, bail};
+
+use crate::paths;
+
+/// Proof that this is the only oxidrop using a port, released when dropped.
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Makes sure no other oxidrop on this machine is using `port`, so a second
+/// instance fails with a clear message instead of a busy port error from deep
+/// inside the service.
+pub fn acquire(port: u16) -> anyhow::Result<InstanceLock> {
+    match try_acquire(port)? {
+        Ok(lock) => Ok(lock),
+        Err(owner) => {
+            let owner = owner.map(|pid| format!(" (pid {pid})")).unwrap_or_default();
+            bail!(
+                "Another oxidrop is already running on port {port}{owner}. \
+                 Stop it first, or pick another port with --port"
+            );
+        }
+    }
+}
+
+/// Like [`acquire`], but tells the pid of the oxidrop using `port`, if known,
+/// instead of failing.
+pub fn try_acquire(port: u16) -> anyhow::Result<Result<InstanceLock, Option<u32>>> {
+    let dir = paths::state_dir()?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("instance-{port}.lock"));
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let owner = fs::read_to_string(&path)
+                .ok()
+                .and_then(|pid| pid.trim().parse().ok());
+            return Ok(Err(owner));
+        }
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {}", path.display()))?
+        }
+    }
+
+    file.set_len(0)?;
+    write!(file, "{}", std::process::id())?;
+    Ok(Ok(InstanceLock { _file: file }))
+}
@@ -0,0 +1,1370 @@
+mod config;
+mod glyphs;
+mod instance;
+mod manifest;
+mod notify;
+mod paths;
+mod pending;
+mod progress_file;
+#[cfg(feature = "otel")]
+mod telemetry;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use glyphs::Glyphs;
+use indexmap::{IndexMap, IndexSet};
+use oxidrop::{
+    Endpoint, Oxidrop, OxidropEvent, Payload, Progress, TransferDirection, TransferOutcome,
+    TransferRequest, TransferState,
+};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Clear, List, ListItem, ListState, Paragraph},
+};
+use scopeguard::defer;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{IntervalStream, ReceiverStream},
+};
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[arg(long, short, env = "OXIDROP_LOG_LEVEL")]
+    log_level: Option<log::LevelFilter>,
+
+    #[arg(long, env = "OXIDROP_LOG_FORMAT", default_value = "full")]
+    log_format: LogFormat,
+
+    /// Use the settings of this profile from the config file
+    #[arg(long, short, env = "OXIDROP_PROFILE")]
+    profile: Option<String>,
+
+    /// Port to listen on [default: 9300, or any free port if another
+    /// oxidrop has that]
+    #[arg(long, env = "OXIDROP_PORT")]
+    port: Option<u16>,
+
+    /// Directory to save received files in
+    #[arg(long, env = "OXIDROP_DOWNLOAD_DIR")]
+    download_dir: Option<PathBuf>,
+
+    /// Whether to show this device to others while receiving [default:
+    /// visible]
+    #[arg(long, env = "OXIDROP_VISIBILITY")]
+    visibility: Option<config::Visibility>,
+
+    /// Keep a JSON snapshot of all transfers in this file, or write one per
+    /// line if it's a FIFO
+    #[arg(long, env = "OXIDROP_PROGRESS_FILE", value_name = "PATH")]
+    progress_file: Option<PathBuf>,
+
+    /// Draw the UI with ASCII characters only. This is the default when the
+    /// locale isn't UTF-8 or on the Linux console
+    #[arg(long, env = "OXIDROP_ASCII")]
+    ascii: bool,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Timestamped lines for a terminal or log file
+    Full,
+    /// Lines with syslog priority prefixes, as understood by journald
+    Systemd,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    Send {
+        #[arg(required_unless_present = "from_file")]
+        files: Vec<PathBuf>,
+        /// Also send the files listed in this file, one per line, or in stdin
+        /// if it's `-`. `PATH => NAME` sends PATH under another name
+        #[arg(long, value_name = "FILE")]
+        from_file: Option<PathBuf>,
+        /// Send to the device with this name as soon as it's found, without
+        /// the interactive UI
+        #[arg(long, value_name = "NAME")]
+        to: Option<String>,
+        /// Send to the device at this address without looking for it first,
+        /// for networks where devices can't find each other. IPv6 addresses
+        /// go in brackets, as in [fe80::1%2]:9300
+        #[arg(long, value_name = "IP:PORT")]
+        addr: Option<SocketAddr>,
+    },
+    Receive {
+        /// Ask for consent through desktop notifications as well
+        #[arg(long)]
+        notify: bool,
+    },
+    /// Print where configuration and data are stored
+    Paths {},
+    /// Read or change the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print a setting, taking --profile into account
+    Get { key: String },
+    /// Change a setting, in the --profile table if one is given
+    Set { key: String, value: String },
+}
+
+/// The port to listen on unless told otherwise.
+const DEFAULT_PORT: u16 = 9300;
+
+/// How long a device or request that went away stays grayed out before it's
+/// removed from the list.
+const STALE_GRACE: Duration = Duration::from_secs(3);
+
+enum AppEvent {
+    NewEndpoint(Endpoint),
+    EndpointLost(Endpoint),
+    NewTransferRequest(TransferRequest),
+    RequestWithdrawn(TransferRequest),
+    ConsentGiven(TransferRequest, bool),
+    Progress(Progress),
+    Outcome(TransferOutcome),
+    TextReceived(Payload),
+    FileConflict {
+        path: PathBuf,
+        saved_as: Option<PathBuf>,
+    },
+    FileRenamed {
+        name: String,
+        path: PathBuf,
+    },
+    InsufficientSpace(TransferRequest, u64),
+    Move(Motion),
+    Confirm,
+    Reject,
+    Toggle,
+    Quit,
+    FinishInBackground,
+    CancelTransfers,
+    Dismiss,
+    Resize,
+    Tick,
+    ConfigReloaded(anyhow::Result<config::Settings>),
+    Error(std::io::Error),
+}
+
+/// How the UI was left.
+enum Exit {
+    Now,
+    /// Transfers in progress should be allowed to finish, or to acknowledge a
+    /// cancellation, before the process exits.
+    AfterTransfers,
+}
+
+/// A cursor movement in a list, with vim's count prefix already applied.
+#[derive(Clone, Copy)]
+enum Motion {
+    Up(u16),
+    Down(u16),
+    HalfPageUp(u16),
+    HalfPageDown(u16),
+    First,
+    Last,
+    /// Zero-based index, from `<count>G` or `<count>gg`.
+    To(usize),
+}
+
+struct AppState {
+    glyphs: &'static Glyphs,
+    endpoints: IndexSet<Endpoint>,
+    /// Files the send screen is going to send.
+    outgoing: Vec<oxidrop::File>,
+    requests: IndexSet<TransferRequest>,
+    device_name: String,
+    list_state: ListState,
+    /// Rows the list had when it was last drawn, for half-page scrolling.
+    list_height: u16,
+    num_dots: usize,
+    status: Option<String>,
+    /// Senders whose requests are listed individually in the receive list.
+    expanded: HashSet<String>,
+    /// Whether quitting is waiting for the user to decide what happens to
+    /// transfers in progress.
+    confirm_quit: bool,
+    /// Entries that went away, with when they did.
+    stale_endpoints: HashMap<Endpoint, Instant>,
+    stale_requests: HashMap<TransferRequest, Instant>,
+    /// Requests from devices named like one a transfer was accepted from
+    /// before.
+    trusted_requests: HashSet<TransferRequest>,
+}
+
+/// A line of the receive list. Requests from a sender with several of them
+/// are collapsed under a header row unless the sender is expanded.
+enum ReceiveRow<'a> {
+    Sender {
+        name: &'a str,
+        requests: Vec<&'a TransferRequest>,
+        expanded: bool,
+    },
+    Request {
+        request: &'a TransferRequest,
+        /// The request's position under its sender's header, if it has one.
+        position: Option<usize>,
+    },
+}
+
+impl ReceiveRow<'_> {
+    fn is_stale(&self, stale_requests: &HashMap<TransferRequest, Instant>) -> bool {
+        match self {
+            ReceiveRow::Sender { requests, .. } => {
+                requests.iter().all(|&r| stale_requests.contains_key(r))
+            }
+            ReceiveRow::Request { request, .. } => stale_requests.contains_key(*request),
+        }
+    }
+
+    fn requests(&self) -> Vec<TransferRequest> {
+        match self {
+            ReceiveRow::Sender { requests, .. } => requests.iter().map(|&r| r.clone()).collect(),
+            ReceiveRow::Request { request, .. } => vec![(*request).clone()],
+        }
+    }
+
+    /// The row's text, with the sender name shortened so it fits in `width`
+    /// columns.
+    fn label(&self, glyphs: &Glyphs, width: usize) -> String {
+        match self {
+            ReceiveRow::Sender {
+                name,
+                requests,
+                expanded,
+            } => {
+                let marker = if *expanded {
+                    glyphs.expanded
+                } else {
+                    glyphs.collapsed
+                };
+                let count = format!(" ({} requests)", requests.len());
+                let name_width = width.saturating_sub(marker.width() + 1 + count.width());
+                format!("{marker} {}{count}", glyphs.truncate(name, name_width))
+            }
+            ReceiveRow::Request {
+                position: Some(i), ..
+            } => format!("    Request {}", i + 1),
+            ReceiveRow::Request { request, .. } => {
+                glyphs.truncate(request.sender_name(), width).into_owned()
+            }
+        }
+    }
+}
+
+fn receive_rows<'a>(
+    requests: &'a IndexSet<TransferRequest>,
+    expanded: &HashSet<String>,
+) -> Vec<ReceiveRow<'a>> {
+    let mut groups: IndexMap<&str, Vec<&TransferRequest>> = IndexMap::new();
+    for request in requests {
+        groups
+            .entry(request.sender_name())
+            .or_default()
+            .push(request);
+    }
+
+    let mut rows = Vec::new();
+    for (name, requests) in groups {
+        if let [request] = requests[..] {
+            rows.push(ReceiveRow::Request {
+                request,
+                position: None,
+            });
+            continue;
+        }
+
+        let expanded = expanded.contains(name);
+        if expanded {
+            rows.push(ReceiveRow::Sender {
+                name,
+                requests: requests.clone(),
+                expanded,
+            });
+            rows.extend(
+                requests
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, request)| ReceiveRow::Request {
+                        request,
+                        position: Some(i),
+                    }),
+            );
+        } else {
+            rows.push(ReceiveRow::Sender {
+                name,
+                requests,
+                expanded,
+            });
+        }
+    }
+    rows
+}
+
+impl AppState {
+    fn new(oxidrop: &Oxidrop, glyphs: &'static Glyphs) -> Self {
+        AppState {
+            glyphs,
+            num_dots: 1,
+            device_name: oxidrop.device_name(),
+            endpoints: IndexSet::new(),
+            outgoing: Vec::new(),
+            requests: IndexSet::new(),
+            list_state: ListState::default(),
+            list_height: 0,
+            status: None,
+            expanded: HashSet::new(),
+            confirm_quit: false,
+            stale_endpoints: HashMap::new(),
+            stale_requests: HashMap::new(),
+            trusted_requests: HashSet::new(),
+        }
+    }
+
+    fn apply(&mut self, motion: Motion) {
+        let half_page = (self.list_height / 2).max(1);
+        match motion {
+            Motion::Up(n) => self.list_state.scroll_up_by(n),
+            Motion::Down(n) => self.list_state.scroll_down_by(n),
+            Motion::HalfPageUp(n) => self.list_state.scroll_up_by(half_page.saturating_mul(n)),
+            Motion::HalfPageDown(n) => self.list_state.scroll_down_by(half_page.saturating_mul(n)),
+            Motion::First => self.list_state.select_first(),
+            Motion::Last => self.list_state.select_last(),
+            Motion::To(i) => self.list_state.select(Some(i)),
+        }
+    }
+
+    /// Removes entries that have been grayed out for long enough.
+    fn prune_stale(&mut self) {
+        self.stale_endpoints.retain(|endpoint, since| {
+            let keep = since.elapsed() < STALE_GRACE;
+            if !keep {
+                self.endpoints.shift_remove(endpoint);
+            }
+            keep
+        });
+        self.stale_requests.retain(|request, since| {
+            let keep = since.elapsed() < STALE_GRACE;
+            if !keep {
+                self.requests.shift_remove(request);
+            }
+            keep
+        });
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `4.5 GiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn format_progress(progress: Progress) -> String {
+    format!(
+        "{} of {} ({:.0}%)",
+        format_bytes(progress.transferred),
+        format_bytes(progress.total),
+        progress.fraction() * 100.0
+    )
+}
+
+fn stale_style() -> Style {
+    Style::new().fg(Color::DarkGray)
+}
+
+/// Terminals narrower than this only get the list, without a details pane.
+const DETAILS_MIN_WIDTH: u16 = 60;
+
+/// Where each part of a screen goes: key help on top, the list with an
+/// optional details pane beside it, and a status bar at the bottom.
+struct Panes {
+    keys: Rect,
+    list: Rect,
+    details: Option<Rect>,
+    status: Rect,
+}
+
+impl Panes {
+    fn new(area: Rect) -> Self {
+        let [keys, body, status] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let (list, details) = if body.width >= DETAILS_MIN_WIDTH {
+            let [list, details] =
+                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(body);
+            (list, Some(details))
+        } else {
+            (body, None)
+        };
+        Panes {
+            keys,
+            list,
+            details,
+            status,
+        }
+    }
+}
+
+fn render_quit_dialog(frame: &mut Frame) {
+    let lines = vec![
+        Line::from("Transfers are still in progress."),
+        Line::from(""),
+        Line::from("Finish in background: <F>"),
+        Line::from("Cancel transfers: <C>"),
+        Line::from("Keep running: <Esc>"),
+    ];
+    let [area] = Layout::vertical([Constraint::Length(lines.len() as u16 + 2)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    let [area] = Layout::horizontal([Constraint::Length(40)])
+        .flex(Flex::Center)
+        .areas(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Quit? ")),
+        area,
+    );
+}
+
+fn key_help(text: String) -> Line<'static> {
+    Line::from(text)
+        .centered()
+        .style(Style::new().add_modifier(Modifier::UNDERLINED))
+}
+
+/// Turns key presses into events, keeping track of vim-style count prefixes
+/// and the first `g` of `gg`.
+#[derive(Default)]
+struct KeyParser {
+    count: Option<u16>,
+    pending_g: bool,
+}
+
+impl KeyParser {
+    fn feed(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Option<AppEvent> {
+        if let KeyCode::Char(c @ '0'..='9') = code
+            && (c != '0' || self.count.is_some())
+        {
+            let digit = c.to_digit(10).unwrap() as u16;
+            self.count = Some(
+                self.count
+                    .unwrap_or(0)
+                    .saturating_mul(10)
+                    .saturating_add(digit),
+            );
+            self.pending_g = false;
+            return None;
+        }
+
+        let count = self.count.take();
+        let n = count.unwrap_or(1);
+        let to_line = |count: Option<u16>, default| {
+            count.map_or(default, |c| Motion::To(usize::from(c.max(1)) - 1))
+        };
+        if std::mem::take(&mut self.pending_g) && code == KeyCode::Char('g') {
+            return Some(AppEvent::Move(to_line(count, Motion::First)));
+        }
+
+        match (code, modifiers) {
+            (KeyCode::Char('g'), KeyModifiers::NONE) => {
+                self.pending_g = true;
+                self.count = count;
+                None
+            }
+            (KeyCode::Char('G'), _) => Some(AppEvent::Move(to_line(count, Motion::Last))),
+            (KeyCode::Home, _) => Some(AppEvent::Move(Motion::First)),
+            (KeyCode::End, _) => Some(AppEvent::Move(Motion::Last)),
+            (KeyCode::Char('d'), KeyModifiers::CONTROL) | (KeyCode::PageDown, _) => {
+                Some(AppEvent::Move(Motion::HalfPageDown(n)))
+            }
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) | (KeyCode::PageUp, _) => {
+                Some(AppEvent::Move(Motion::HalfPageUp(n)))
+            }
+            (KeyCode::Up | KeyCode::Char('k'), _) => Some(AppEvent::Move(Motion::Up(n))),
+            (KeyCode::Down | KeyCode::Char('j'), _) => Some(AppEvent::Move(Motion::Down(n))),
+            (KeyCode::Enter, _) => Some(AppEvent::Confirm),
+            (KeyCode::Char('f'), _) => Some(AppEvent::FinishInBackground),
+            (KeyCode::Char('c'), KeyModifiers::NONE) => Some(AppEvent::CancelTransfers),
+            (KeyCode::Esc, _) => Some(AppEvent::Dismiss),
+            (KeyCode::Char('r') | KeyCode::Delete, _) => Some(AppEvent::Reject),
+            (KeyCode::Char(' ') | KeyCode::Tab, _) => Some(AppEvent::Toggle),
+            (KeyCode::Char('q'), _) | (KeyCode::Char('c'), KeyModifiers::CONTROL) => {
+                Some(AppEvent::Quit)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn get_input_stream() -> impl Stream<Item = AppEvent> {
+    let mut keys = KeyParser::default();
+    EventStream::new().filter_map(move |e| match e {
+        Ok(Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        })) => keys.feed(code, modifiers),
+        Ok(Event::Resize(_, _)) => Some(AppEvent::Resize),
+        Err(e) => Some(AppEvent::Error(e)),
+        _ => None,
+    })
+}
+
+fn get_interval_stream() -> impl Stream<Item = AppEvent> {
+    IntervalStream::new(tokio::time::interval(Duration::from_secs(1))).map(|_| AppEvent::Tick)
+}
+
+fn render_send(
+    AppState {
+        glyphs,
+        endpoints,
+        outgoing,
+        list_state,
+        list_height,
+        num_dots,
+        status,
+        confirm_quit,
+        stale_endpoints,
+        ..
+    }: &mut AppState,
+    frame: &mut Frame,
+) {
+    let panes = Panes::new(frame.area());
+    frame.render_widget(
+        key_help(format!(
+            "Select: <{}>/<{}>/<J>/<K>  Send Files: <Enter>  Cancel: <R>  Quit: <Q>/<Ctrl-C>",
+            glyphs.up, glyphs.down
+        )),
+        panes.keys,
+    );
+
+    let block = Block::bordered().title(" Devices ");
+    let inner = block.inner(panes.list);
+    *list_height = inner.height;
+    if endpoints.is_empty() {
+        let searching = Line::from(format!("Discovering devices{}", ".".repeat(*num_dots)));
+        frame.render_widget(Paragraph::new(searching).block(block), panes.list);
+    } else {
+        let items = endpoints.iter().map(|e| {
+            let item = ListItem::new(glyphs.truncate(e.name(), inner.width.into()));
+            if stale_endpoints.contains_key(e) {
+                item.style(stale_style())
+            } else {
+                item
+            }
+        });
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, panes.list, list_state);
+    }
+
+    if let Some(area) = panes.details {
+        let block = Block::bordered().title(" Files ");
+        let width = block.inner(area).width.into();
+        let files = outgoing
+            .iter()
+            .map(|f| Line::from(glyphs.truncate(&describe_file(f), width).into_owned()))
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(files).block(block), area);
+    }
+
+    let status = match status {
+        Some(status) => status.clone(),
+        None if outgoing.len() == 1 => "1 file to send".to_string(),
+        None => format!("{} files to send", outgoing.len()),
+    };
+    let status = glyphs.truncate(&status, panes.status.width.into());
+    frame.render_widget(Line::from(status), panes.status);
+
+    if *confirm_quit {
+        render_quit_dialog(frame);
+    }
+}
+
+/// How long `send --to` looks for the device before giving up.
+const FIND_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn describe_file(file: &oxidrop::File) -> String {
+    match &file.display_name {
+        Some(name) => format!("{} => {name}", file.path.display()),
+        None => file.path.display().to_string(),
+    }
+}
+
+/// Looks for a device named `name` for up to [`FIND_TIMEOUT`].
+async fn find_endpoint(oxidrop: &Oxidrop, name: &str) -> anyhow::Result<Endpoint> {
+    eprintln!("Looking for {name}...");
+    let mut endpoints = oxidrop.discover_endpoints().await?;
+    tokio::time::timeout(FIND_TIMEOUT, async {
+        while let Some(endpoint) = endpoints.next().await {
+            if endpoint.name() == name {
+                return Some(endpoint);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+    .with_context(|| format!("Couldn't find a device named {name:?}"))
+}
+
+/// Sends `files` to `endpoint` without the interactive UI, returning once
+/// the transfer has ended.
+async fn send_to(
+    oxidrop: &Oxidrop,
+    endpoint: &Endpoint,
+    files: Vec<oxidrop::File>,
+) -> anyhow::Result<()> {
+    let name = endpoint.name();
+    let mut events = oxidrop.events();
+    let count = files.len();
+    let handle = oxidrop.send_files(endpoint, files.into_iter()).await?;
+    eprintln!("Sending {count} file(s) to {name}, waiting for it to accept");
+
+    let mut pin_shown = false;
+    while let Some(event) = events.next().await {
+        match event {
+            OxidropEvent::TransferProgress { id, progress, .. } if id == handle.id() => {
+                eprint!("\rSent {}   ", format_progress(progress))
+            }
+            OxidropEvent::TransferStateChanged { id, state, .. } if id == handle.id() => {
+                // The PIN arrives with whichever state is reported first.
+                if !pin_shown && let Some(pin_code) = oxidrop.pin_code(&handle) {
+                    eprintln!("Check that {name} shows the PIN {pin_code}");
+                    pin_shown = true;
+                }
+                let Some(outcome) = TransferOutcome::from_state(state) else {
+                    continue;
+                };
+                eprintln!();
+                if outcome == TransferOutcome::Finished {
+                    eprintln!("{}", describe_outcome(outcome, name));
+                    return Ok(());
+                }
+                anyhow::bail!("{}", describe_outcome(outcome, name));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn describe_outcome(outcome: TransferOutcome, receiver: &str) -> String {
+    match outcome {
+        TransferOutcome::Finished => format!("Sent to {receiver}"),
+        TransferOutcome::Declined => format!("{receiver} declined the transfer"),
+        TransferOutcome::Cancelled => "Transfer cancelled".to_string(),
+        TransferOutcome::Disconnected => format!("Lost the connection to {receiver}"),
+        TransferOutcome::TimedOut => format!("Gave up waiting for {receiver}"),
+    }
+}
+
+async fn do_send(
+    oxidrop: &Oxidrop,
+    term: Arc<Mutex<DefaultTerminal>>,
+    glyphs: &'static Glyphs,
+    files: Vec<oxidrop::File>,
+) -> anyhow::Result<Exit> {
+    let mut state = AppState::new(oxidrop, glyphs);
+    state.outgoing = files;
+    let mut stream = oxidrop
+        .discover_endpoints()
+        .await?
+        .map(AppEvent::NewEndpoint)
+        .merge(oxidrop.events().filter_map(|e| match e {
+            OxidropEvent::EndpointLost(e) => Some(AppEvent::EndpointLost(e)),
+            OxidropEvent::TransferProgress { progress, .. } => Some(AppEvent::Progress(progress)),
+            OxidropEvent::TransferStateChanged {
+                direction: TransferDirection::Outbound,
+                state,
+                ..
+            } => TransferOutcome::from_state(state).map(AppEvent::Outcome),
+            _ => None,
+        }))
+        .merge(get_input_stream())
+        .merge(get_interval_stream());
+
+    let mut sending = None;
+    while let Some(ev) = stream.next().await {
+        match ev {
+            AppEvent::NewEndpoint(e) => {
+                state.stale_endpoints.remove(&e);
+                state.endpoints.insert(e);
+
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::EndpointLost(e) => {
+                if state.endpoints.contains(&e) {
+                    state.stale_endpoints.insert(e, Instant::now());
+                }
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Move(motion) => {
+                state.apply(motion);
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Confirm => {
+                let Some(i) = state.list_state.selected() else {
+                    continue;
+                };
+                let files = state.outgoing.iter().cloned();
+                let endpoint = &state.endpoints[i];
+                let handle = oxidrop.send_files(endpoint, files).await?;
+                sending = Some((handle, endpoint.name().to_string()));
+            }
+            AppEvent::Reject => {
+                let Some((handle, _)) = sending.take() else {
+                    continue;
+                };
+                state.status = Some(match oxidrop.cancel_transfer(&handle).await? {
+                    TransferState::Cancelled => "Transfer cancelled".to_string(),
+                    ended => format!("Transfer had already ended: {ended:?}"),
+                });
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Outcome(outcome) => {
+                let receiver = sending
+                    .take()
+                    .map_or_else(|| "Receiver".to_string(), |(_, name)| name);
+                state.status = Some(describe_outcome(outcome, &receiver));
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Toggle => continue,
+            AppEvent::Quit if !oxidrop.has_active_transfers() => break,
+            AppEvent::Quit => {
+                state.confirm_quit = true;
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::FinishInBackground if state.confirm_quit => {
+                return Ok(Exit::AfterTransfers);
+            }
+            AppEvent::CancelTransfers if state.confirm_quit => {
+                oxidrop.cancel_all_transfers()?;
+                return Ok(Exit::AfterTransfers);
+            }
+            AppEvent::Dismiss => {
+                state.confirm_quit = false;
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::FinishInBackground | AppEvent::CancelTransfers => continue,
+            AppEvent::Resize => {
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Tick => {
+                state.num_dots = state.num_dots % 3 + 1;
+                state.prune_stale();
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Progress(progress) => {
+                state.status = Some(format!("Transferred {}", format_progress(progress)));
+                term.lock().unwrap().draw(|f| render_send(&mut state, f))?;
+            }
+            AppEvent::Error(e) => Err(e)?,
+            AppEvent::NewTransferRequest(_)
+            | AppEvent::RequestWithdrawn(_)
+            | AppEvent::ConsentGiven(..)
+            | AppEvent::TextReceived(_)
+            | AppEvent::FileConflict { .. }
+            | AppEvent::FileRenamed { .. }
+            | AppEvent::InsufficientSpace(..)
+            | AppEvent::ConfigReloaded(_) => unreachable!(),
+        }
+    }
+
+    Ok(Exit::Now)
+}
+
+fn render_receive(
+    AppState {
+        glyphs,
+        requests,
+        device_name,
+        list_state,
+        list_height,
+        num_dots,
+        status,
+        expanded,
+        confirm_quit,
+        stale_requests,
+        trusted_requests,
+        ..
+    }: &mut AppState,
+    frame: &mut Frame,
+) {
+    let panes = Panes::new(frame.area());
+    frame.render_widget(
+        key_help(format!(
+            "Select: <{}>/<{}>/<J>/<K>  Accept: <Enter>  Reject: <R>  Expand: <Space>  Cancel: <C>  Quit: <Q>/<Ctrl-C>",
+            glyphs.up, glyphs.down
+        )),
+        panes.keys,
+    );
+
+    let block = Block::bordered().title(" Requests ");
+    let inner = block.inner(panes.list);
+    *list_height = inner.height;
+    let rows = receive_rows(requests, expanded);
+    if rows.is_empty() {
+        let waiting = Line::from(format!("Waiting for requests{}", ".".repeat(*num_dots)));
+        frame.render_widget(Paragraph::new(waiting).block(block), panes.list);
+    } else {
+        let items = rows.iter().map(|row| {
+            let item = ListItem::new(row.label(glyphs, inner.width.into()));
+            if row.is_stale(stale_requests) {
+                item.style(stale_style())
+            } else {
+                item
+            }
+        });
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, panes.list, list_state);
+    }
+
+    if let Some(area) = panes.details {
+        let block = Block::bordered().title(" Details ");
+        let width = usize::from(block.inner(area).width);
+        let selected = list_state
+            .selected()
+            .and_then(|i| rows.get(i.min(rows.len().saturating_sub(1))));
+        let lines = match selected {
+            Some(row) => {
+                let requests = row.requests();
+                let from = "From: ";
+                let name =
+                    glyphs.truncate(requests[0].sender_name(), width.saturating_sub(from.len()));
+                let mut lines = vec![Line::from(format!("{from}{name}"))];
+                match requests.as_slice() {
+                    [request] => {
+                        if !request.files().is_empty() {
+                            lines.push(Line::from(format!("Files: {}", request.files().len())));
+                        }
+                        if trusted_requests.contains(request) {
+                            lines.push(Line::from("Same name as a device accepted before"));
+                        }
+                        if let Some(pin_code) = request.pin_code() {
+                            lines.push(Line::from(format!("PIN: {pin_code}")));
+                        }
+                        if request.total_bytes() > 0 {
+                            lines.push(Line::from(format!(
+                                "Size: {}",
+                                format_bytes(request.total_bytes())
+                            )));
+                        }
+                        if let Some(text) = request.text() {
+                            let label = "Text: ";
+                            let first_line = text.lines().next().unwrap_or_default();
+                            let text =
+                                glyphs.truncate(first_line, width.saturating_sub(label.len()));
+                            lines.push(Line::from(format!("{label}{text}")));
+                        }
+                    }
+                    _ => lines.push(Line::from(format!("Requests: {}", requests.len()))),
+                }
+                lines
+            }
+            None => vec![],
+        };
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    let status = status
+        .clone()
+        .unwrap_or_else(|| format!("This device is shown as {device_name}"));
+    let status = glyphs.truncate(&status, panes.status.width.into());
+    frame.render_widget(Line::from(status), panes.status);
+
+    if *confirm_quit {
+        render_quit_dialog(frame);
+    }
+}
+
+async fn do_receive(
+    oxidrop: &Oxidrop,
+    term: Arc<Mutex<DefaultTerminal>>,
+    glyphs: &'static Glyphs,
+    settings: config::Settings,
+    config_updates: impl Stream<Item = anyhow::Result<config::Settings>> + Unpin,
+    notify: bool,
+) -> anyhow::Result<Exit> {
+    oxidrop
+        .set_visibility(
+            settings
+                .visibility
+                .map_or(oxidrop::Visibility::Visible, Into::into),
+        )
+        .await?;
+    let mut state = AppState::new(oxidrop, glyphs);
+    let mut lost = pending::load_lost().unwrap_or_else(|e| {
+        log::warn!("Failed to load pending requests: {e:#}");
+        vec![]
+    });
+    for record in &lost {
+        log::warn!(
+            "Request {} from {} was never answered before oxidrop stopped",
+            record.id,
+            record.sender
+        );
+    }
+    if !lost.is_empty() {
+        state.status = Some(format!(
+            "{} pending request(s) were lost when oxidrop last stopped",
+            lost.len()
+        ));
+    }
+    let mut pending_store = pending::PendingStore::default();
+    let (consent_send, consent_recv) = tokio::sync::mpsc::channel(10);
+    let mut stream = oxidrop
+        .get_transfer_requests()?
+        .map(AppEvent::NewTransferRequest)
+        .merge(oxidrop.events().filter_map(|e| match e {
+            OxidropEvent::TransferRequestWithdrawn(r) => Some(AppEvent::RequestWithdrawn(r)),
+            OxidropEvent::TransferProgress {
+                direction: TransferDirection::Inbound,
+                progress,
+                ..
+            } => Some(AppEvent::Progress(progress)),
+            OxidropEvent::TextReceived { payload, .. } => Some(AppEvent::TextReceived(payload)),
+            OxidropEvent::FileConflict { path, saved_as, .. } => {
+                Some(AppEvent::FileConflict { path, saved_as })
+            }
+            OxidropEvent::FileRenamed { name, path, .. } => {
+                Some(AppEvent::FileRenamed { name, path })
+            }
+            OxidropEvent::InsufficientSpace { request, available } => {
+                Some(AppEvent::InsufficientSpace(request, available))
+            }
+            _ => None,
+        }))
+        .merge(get_input_stream())
+        .merge(get_interval_stream())
+        .merge(config_updates.map(AppEvent::ConfigReloaded))
+        .merge(ReceiverStream::new(consent_recv));
+
+    // Accepted requests, most recent last.
+    let mut receiving = Vec::<TransferRequest>::new();
+    while let Some(ev) = stream.next().await {
+        // Catches up with whatever the previous event changed; ticks make sure
+        // that happens within a second.
+        pending_store.sync(&state.requests);
+        match ev {
+            AppEvent::NewTransferRequest(req) => {
+                if notify && !state.requests.contains(&req) {
+                    let consent_send = consent_send.clone();
+                    let req = req.clone();
+                    tokio::spawn(async move {
+                        if let Some(accept) = notify::ask_consent(req.clone()).await {
+                            let _ = consent_send.send(AppEvent::ConsentGiven(req, accept)).await;
+                        }
+                    });
+                }
+
+                if let Some(i) = lost.iter().position(|r| r.is_retried_by(&req)) {
+                    let record = lost.swap_remove(i);
+                    state.status = Some(format!(
+                        "{} sent a request again that was lost when oxidrop stopped",
+                        record.sender
+                    ));
+                }
+                if oxidrop.is_trusted(&req) {
+                    state.trusted_requests.insert(req.clone());
+                }
+                state.requests.insert(req);
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::RequestWithdrawn(req) => {
+                if state.requests.contains(&req) {
+                    state.stale_requests.insert(req, Instant::now());
+                }
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Move(motion) => {
+                state.apply(motion);
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Confirm | AppEvent::Reject => {
+                let Some(i) = state.list_state.selected() else {
+                    continue;
+                };
+                let Some(selected) = receive_rows(&state.requests, &state.expanded)
+                    .get(i)
+                    .map(ReceiveRow::requests)
+                else {
+                    continue;
+                };
+                for req in selected {
+                    if let AppEvent::Confirm = ev {
+                        oxidrop.accept_transfer(&req).await?;
+                        receiving.push(req.clone());
+                    } else {
+                        oxidrop.reject_transfer(&req).await?;
+                    }
+                    state.requests.shift_remove(&req);
+                }
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Toggle => {
+                let Some(i) = state.list_state.selected() else {
+                    continue;
+                };
+                if let Some(ReceiveRow::Sender { name, .. }) =
+                    receive_rows(&state.requests, &state.expanded).get(i)
+                {
+                    let name = name.to_string();
+                    if !state.expanded.remove(&name) {
+                        state.expanded.insert(name);
+                    }
+                }
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::ConsentGiven(req, accept) => {
+                if accept {
+                    oxidrop.accept_transfer(&req).await?;
+                    receiving.push(req.clone());
+                } else {
+                    oxidrop.reject_transfer(&req).await?;
+                }
+                state.requests.shift_remove(&req);
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Quit if !oxidrop.has_active_transfers() => break,
+            AppEvent::Quit => {
+                state.confirm_quit = true;
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::FinishInBackground if state.confirm_quit => {
+                return Ok(Exit::AfterTransfers);
+            }
+            AppEvent::CancelTransfers if state.confirm_quit => {
+                oxidrop.cancel_all_transfers()?;
+                return Ok(Exit::AfterTransfers);
+            }
+            AppEvent::Dismiss => {
+                state.confirm_quit = false;
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::TextReceived(payload) => {
+                state.status = Some(match payload {
+                    Payload::Url(url) => format!("Received link: {url}"),
+                    Payload::Wifi(network) => format!("Received Wi-Fi network: {network}"),
+                    Payload::Text(text) => {
+                        format!("Received text: {}", text.lines().next().unwrap_or_default())
+                    }
+                    Payload::Files(_) => continue,
+                });
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::FileConflict { path, saved_as } => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                state.status = Some(match saved_as {
+                    Some(saved_as) if saved_as == path => format!("Replaced {name}"),
+                    Some(saved_as) => format!(
+                        "{name} already existed, saved as {}",
+                        saved_as.file_name().unwrap_or_default().to_string_lossy()
+                    ),
+                    None => format!("{name} already existed, kept it"),
+                });
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::FileRenamed { name, path } => {
+                state.status = Some(format!(
+                    "Saved {name:?} as {}",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ));
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::InsufficientSpace(req, available) => {
+                state.status = Some(format!(
+                    "{} wants to send {}, but only {} is free",
+                    req.sender_name(),
+                    format_bytes(req.total_bytes()),
+                    format_bytes(available)
+                ));
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::CancelTransfers => {
+                // Cancels the most recently accepted transfer still running.
+                let Some(req) = receiving.pop() else {
+                    continue;
+                };
+                state.status = Some(match oxidrop.cancel_incoming(&req).await? {
+                    TransferState::Cancelled => {
+                        format!("Cancelled receiving from {}", req.sender_name())
+                    }
+                    ended => format!("Transfer had already ended: {ended:?}"),
+                });
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::FinishInBackground => continue,
+            AppEvent::Resize => {
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Tick => {
+                state.num_dots = state.num_dots % 3 + 1;
+                state.prune_stale();
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::ConfigReloaded(Ok(new_settings)) => {
+                let needs_restart = oxidrop.reload_config(new_settings.core_config()?).await?;
+                state.status = Some(if needs_restart.is_empty() {
+                    "Config reloaded".to_string()
+                } else {
+                    format!(
+                        "Config reloaded, restart to apply {}",
+                        needs_restart.join(", ")
+                    )
+                });
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::ConfigReloaded(Err(e)) => {
+                state.status = Some(format!("Config not reloaded: {e:#}"));
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Progress(progress) => {
+                state.status = Some(format!("Received {}", format_progress(progress)));
+                term.lock()
+                    .unwrap()
+                    .draw(|f| render_receive(&mut state, f))?;
+            }
+            AppEvent::Error(e) => Err(e)?,
+            AppEvent::NewEndpoint(_) | AppEvent::EndpointLost(_) | AppEvent::Outcome(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    Ok(Exit::Now)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let mut log_cfg = env_logger::builder();
+    if let Some(log_level) = cli.log_level {
+        log_cfg.filter_level(log_level);
+    }
+    if let LogFormat::Systemd = cli.log_format {
+        log_cfg.format(|buf, record| {
+            let priority = match record.level() {
+                log::Level::Error => 3,
+                log::Level::Warn => 4,
+                log::Level::Info => 6,
+                log::Level::Debug | log::Level::Trace => 7,
+            };
+            writeln!(buf, "<{priority}>{}: {}", record.target(), record.args())
+        });
+    }
+    log_cfg.init();
+
+    if let Commands::Paths {} = cli.command {
+        println!("config: {}", paths::config_file()?.display());
+        println!("data:   {}", paths::data_dir()?.display());
+        println!("state:  {}", paths::state_dir()?.display());
+        return Ok(());
+    }
+    if let Commands::Receive { notify: true } = cli.command
+        && !cfg!(feature = "notifications")
+    {
+        anyhow::bail!("--notify needs oxidrop to be built with the notifications feature");
+    }
+    if let Commands::Config { command } = &cli.command {
+        match command {
+            ConfigCommand::Get { key } => {
+                if let Some(value) = config::get(cli.profile.as_deref(), key)? {
+                    println!("{value}");
+                }
+            }
+            ConfigCommand::Set { key, value } => config::set(cli.profile.as_deref(), key, value)?,
+        }
+        return Ok(());
+    }
+
+    // Command line and environment take precedence over the config file.
+    let overrides = config::Settings {
+        port: cli.port,
+        download_dir: cli.download_dir,
+        visibility: cli.visibility,
+        ..Default::default()
+    };
+    let settings = config::load(cli.profile.as_deref())?.overridden_by(overrides.clone());
+    let (port, _instance) = match settings.port {
+        Some(port) => (Some(port), Some(instance::acquire(port)?)),
+        None => match instance::try_acquire(DEFAULT_PORT)? {
+            Ok(lock) => (Some(DEFAULT_PORT), Some(lock)),
+            // Without a port asked for, run next to the other instance on
+            // any free port.
+            Err(owner) => {
+                log::info!(
+                    "Another oxidrop{} is using port {DEFAULT_PORT}, picking another",
+                    owner.map(|pid| format!(" (pid {pid})")).unwrap_or_default()
+                );
+                (None, None)
+            }
+        },
+    };
+
+    let glyphs = glyphs::select(cli.ascii);
+    let init_term = || {
+        Arc::new(Mutex::new(ratatui::init_with_options(
+            ratatui::TerminalOptions {
+                viewport: ratatui::Viewport::Inline(12),
+            },
+        )))
+    };
+    defer! {
+        ratatui::restore();
+    }
+
+    let config = settings.core_config()?;
+    let send_only = matches!(cli.command, Commands::Send { .. });
+    let oxidrop = Oxidrop::new(oxidrop::Config {
+        port,
+        send_only,
+        visibility: if send_only {
+            oxidrop::Visibility::Invisible
+        } else {
+            config.visibility
+        },
+        ..config
+    })
+    .await?;
+    #[cfg(feature = "otel")]
+    let _telemetry = telemetry::init(&oxidrop)?;
+    if let Some(path) = cli.progress_file {
+        progress_file::spawn(path, oxidrop.events());
+    }
+    let exit = match cli.command {
+        Commands::Send {
+            files,
+            from_file,
+            to,
+            addr,
+        } => {
+            let mut files = files
+                .into_iter()
+                .map(oxidrop::File::new)
+                .collect::<Vec<_>>();
+            if let Some(from_file) = from_file {
+                files.extend(manifest::read(&from_file)?);
+            }
+            if files.is_empty() {
+                anyhow::bail!("No files to send");
+            }
+
+            #[cfg(feature = "otel")]
+            let _session = telemetry::discovery_session();
+            let endpoint = match (addr, to) {
+                (Some(addr), name) => Some(Endpoint::from_addr(
+                    addr,
+                    &name.unwrap_or_else(|| addr.to_string()),
+                )),
+                (None, Some(name)) => Some(find_endpoint(&oxidrop, &name).await?),
+                (None, None) => None,
+            };
+            match endpoint {
+                Some(endpoint) => {
+                    send_to(&oxidrop, &endpoint, files).await?;
+                    Exit::Now
+                }
+                None => do_send(&oxidrop, init_term(), glyphs, files).await?,
+            }
+        }
+        Commands::Receive { notify } => {
+            let config_updates = config::watch(cli.profile, overrides);
+            do_receive(
+                &oxidrop,
+                init_term(),
+                glyphs,
+                settings,
+                config_updates,
+                notify,
+            )
+            .await?
+        }
+        Commands::Paths {} | Commands::Config { .. } => unreachable!(),
+    };
+
+    if let Exit::AfterTransfers = exit {
+        ratatui::restore();
+        eprintln!("Waiting for transfers to finish, press Ctrl-C to stop now");
+        oxidrop.wait_for_transfers().await;
+    }
+
+    Ok(())
+}
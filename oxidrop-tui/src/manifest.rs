@@ -0,0 +1,140 @@
+//! Lists of files to send, read from a file or stdin by `send --from-file`.
+//!
+//! Each line names one file. A file can be sent under another name by
+//! following the path with `=>` and the name, with whitespace on both sides
+//! of the `=>`:
+//!
+//! ```text
+//! # Lines starting with # and blank lines are skipped
+//! photos/IMG_0001.jpg
+//! report-final-v3.pdf => report.pdf
+//! odd=>name.txt
+//! a => b.txt => b.txt
+//! ```
+//!
+//! An `=>` without whitespace around it is part of the path, and only the
+//! last separated one renames, so a path that contains ` => ` itself is
+//! given with a name.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+
+const RENAME_SEPARATOR: &str = "=>";
+
+/// Reads the manifest at `source`, or stdin if it's `-`.
+pub fn read(source: &Path) -> anyhow::Result<Vec<oxidrop::File>> {
+    let contents = if source == Path::new("-") {
+        let mut s = String::new();
+        io::stdin()
+            .read_to_string(&mut s)
+            .context("Failed to read the file list from stdin")?;
+        s
+    } else {
+        fs::read_to_string(source)
+            .with_context(|| format!("Failed to read {}", source.display()))?
+    };
+    parse(&contents)
+}
+
+fn parse(contents: &str) -> anyhow::Result<Vec<oxidrop::File>> {
+    let mut files = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (path, name) = match split_rename(line) {
+            Some((path, name)) => (path.trim_end(), Some(name.trim_start())),
+            None => (line, None),
+        };
+        if path.is_empty() || name.is_some_and(str::is_empty) {
+            bail!("Line {}: expected `PATH` or `PATH => NAME`", i + 1);
+        }
+
+        let mut file = oxidrop::File::new(path);
+        file.display_name = name.map(str::to_string);
+        files.push(file);
+    }
+    Ok(files)
+}
+
+/// Splits `line` at the last [`RENAME_SEPARATOR`] that has whitespace or the
+/// end of the line on both sides.
+fn split_rename(line: &str) -> Option<(&str, &str)> {
+    let (i, _) = line.rmatch_indices(RENAME_SEPARATOR).find(|&(i, sep)| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + sep.len()..].chars().next();
+        before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+    })?;
+    Some((&line[..i], &line[i + RENAME_SEPARATOR.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn entries(contents: &str) -> Vec<(PathBuf, Option<String>)> {
+        parse(contents)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.path, f.display_name))
+            .collect()
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        assert_eq!(
+            entries("# files\n\n  photos/IMG_0001.jpg  \n"),
+            [(PathBuf::from("photos/IMG_0001.jpg"), None)]
+        );
+    }
+
+    #[test]
+    fn renames_after_the_separator() {
+        assert_eq!(
+            entries("report-final-v3.pdf   =>  report.pdf"),
+            [(
+                PathBuf::from("report-final-v3.pdf"),
+                Some("report.pdf".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn keeps_arrows_without_whitespace_in_the_path() {
+        assert_eq!(
+            entries("odd=>name.txt\nodd=>name.txt => plain.txt"),
+            [
+                (PathBuf::from("odd=>name.txt"), None),
+                (
+                    PathBuf::from("odd=>name.txt"),
+                    Some("plain.txt".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn renames_at_the_last_separator() {
+        assert_eq!(
+            entries("a => b.txt => b.txt"),
+            [(PathBuf::from("a => b.txt"), Some("b.txt".to_string()))]
+        );
+    }
+
+    #[test]
+    fn reports_lines_missing_a_path_or_name() {
+        for line in ["=> name.txt", "file.txt =>", "  =>  "] {
+            let e = parse(&format!("ok.txt\n{line}")).unwrap_err();
+            assert!(e.to_string().starts_with("Line 2:"), "{line:?}: {e}");
+        }
+    }
+}
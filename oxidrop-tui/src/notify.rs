@@ -0,0 +1,72 @@
+use oxidrop::TransferRequest;
+
+/// Shows a desktop notification for `request` with Accept and Reject buttons
+/// and waits for one of them to be pressed.
+///
+/// Only freedesktop notifications have buttons. On Windows and macOS the
+/// notification is shown without them and this returns `None` right away, so
+/// the request is answered in the terminal as usual. It also returns `None`
+/// if the notification was dismissed.
+#[cfg(feature = "notifications")]
+pub async fn ask_consent(request: TransferRequest) -> Option<bool> {
+    use notify_rust::Notification;
+
+    tokio::task::spawn_blocking(move || {
+        let mut notification = Notification::new();
+        notification
+            .appname("oxidrop")
+            .summary("Incoming transfer")
+            .body(&body(&request));
+
+        #[cfg(all(unix, not(target_os = "macos")))]
+        {
+            let mut answer = None;
+            notification
+                .action("accept", "Accept")
+                .action("reject", "Reject")
+                .show()
+                .ok()?
+                .wait_for_action(|action| {
+                    answer = match action {
+                        "accept" => Some(true),
+                        "reject" => Some(false),
+                        _ => None,
+                    }
+                });
+            answer
+        }
+
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        {
+            let _ = notification.show();
+            None
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// What `request` offers, from whom.
+#[cfg(feature = "notifications")]
+fn body(request: &TransferRequest) -> String {
+    use oxidrop::Payload;
+
+    let sender = request.sender_name();
+    match request.payload() {
+        Payload::Files(names) if names.len() == 1 => {
+            format!("{sender} wants to send you {}", names[0])
+        }
+        Payload::Files(names) => format!("{sender} wants to send you {} files", names.len()),
+        Payload::Text(_) => format!("{sender} wants to send you some text"),
+        Payload::Url(url) => format!("{sender} wants to send you a link: {url}"),
+        Payload::Wifi(network) => {
+            format!("{sender} wants to share the Wi-Fi network {network}")
+        }
+    }
+}
+
+#[cfg(not(feature = "notifications"))]
+pub async fn ask_consent(_request: TransferRequest) -> Option<bool> {
+    None
+}
@@ -0,0 +1,39 @@
+//! Where oxidrop keeps its files.
+//!
+//! Locations follow the platform's conventions, which on Linux are the XDG
+//! base directories:
+//!
+//! | Directory | Linux default              | Contents                                                  |
+//! |-----------|----------------------------|-----------------------------------------------------------|
+//! | config    | `~/.config/oxidrop`        | `config.toml`, written by hand or `config set`            |
+//! | data      | `~/.local/share/oxidrop`   | records worth backing up: accepted device names           |
+//! | state     | `~/.local/state/oxidrop`   | resumable state: pending requests; `instance-<port>.lock` |
+//!
+//! Platforms without a state directory use the local data directory instead.
+//! Everything locates its files through this module, so if the layout ever
+//! changes, migrating from the old location belongs here too.
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use directories::ProjectDirs;
+
+fn project_dirs() -> anyhow::Result<ProjectDirs> {
+    ProjectDirs::from("", "", "oxidrop").context("Failed to find the home directory")
+}
+
+pub fn config_file() -> anyhow::Result<PathBuf> {
+    Ok(project_dirs()?.config_dir().join("config.toml"))
+}
+
+pub fn data_dir() -> anyhow::Result<PathBuf> {
+    Ok(project_dirs()?.data_dir().to_path_buf())
+}
+
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    let dirs = project_dirs()?;
+    Ok(dirs
+        .state_dir()
+        .unwrap_or_else(|| dirs.data_local_dir())
+        .to_path_buf())
+}
@@ -0,0 +1,102 @@
+//! Keeps a record of the requests waiting for consent in the state directory,
+//! so if oxidrop stops before they're answered, the next run can tell what was
+//! lost and recognize the requests when their senders retry.
+
+use std::{collections::HashSet, fs, io, path::PathBuf};
+
+use anyhow::Context;
+use indexmap::IndexSet;
+use oxidrop::TransferRequest;
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub id: String,
+    pub sender: String,
+    #[serde(default)]
+    pub files: Vec<String>,
+    pub text: Option<String>,
+}
+
+impl Record {
+    fn new(request: &TransferRequest) -> Self {
+        Record {
+            id: request.id().to_string(),
+            sender: request.sender_name().to_string(),
+            files: request.files().to_vec(),
+            text: request.text().map(str::to_string),
+        }
+    }
+
+    /// Whether `request` looks like this one sent again. Retries get new ids,
+    /// so this compares what was offered instead.
+    pub fn is_retried_by(&self, request: &TransferRequest) -> bool {
+        self.sender == request.sender_name()
+            && self.files == request.files()
+            && self.text.as_deref() == request.text()
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PendingFile {
+    #[serde(default)]
+    requests: Vec<Record>,
+}
+
+fn pending_file() -> anyhow::Result<PathBuf> {
+    Ok(paths::state_dir()?.join("pending.toml"))
+}
+
+/// Returns the requests that were still pending when oxidrop last stopped.
+pub fn load_lost() -> anyhow::Result<Vec<Record>> {
+    let path = pending_file()?;
+    match fs::read_to_string(&path) {
+        Ok(s) => Ok(toml::from_str::<PendingFile>(&s)
+            .with_context(|| format!("Failed to parse {}", path.display()))?
+            .requests),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Mirrors the pending requests to disk, rewriting the file only when the set
+/// of requests changes.
+#[derive(Default)]
+pub struct PendingStore {
+    saved: Option<HashSet<String>>,
+}
+
+impl PendingStore {
+    pub fn sync(&mut self, requests: &IndexSet<TransferRequest>) {
+        let ids = requests.iter().map(|r| r.id().to_string()).collect();
+        if self.saved.as_ref() == Some(&ids) {
+            return;
+        }
+        let file = PendingFile {
+            requests: requests.iter().map(Record::new).collect(),
+        };
+        match save(&file) {
+            Ok(()) => self.saved = Some(ids),
+            Err(e) => log::warn!("Failed to save pending requests: {e:#}"),
+        }
+    }
+}
+
+fn save(file: &PendingFile) -> anyhow::Result<()> {
+    let path = pending_file()?;
+    if file.requests.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, toml::to_string(file)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,182 @@
+//! `--progress-file`: the state of every transfer as JSON, for status bars and
+//! dashboards.
+//!
+//! A regular file is replaced with a fresh snapshot on every change, so
+//! readers always see a complete document. A FIFO gets one snapshot per line
+//! instead, which suits status bars that read a command's output continuously.
+//! Either way a snapshot looks like:
+//!
+//! ```json
+//! {"transfers":[{"id":"...","direction":"inbound","state":"transferring","transferred":1024,"total":4096}]}
+//! ```
+//!
+//! Transfers are listed once more after reaching a final state, then dropped.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use oxidrop::{OxidropEvent, TransferDirection, TransferState};
+use serde::Serialize;
+use tokio_stream::{Stream, StreamExt};
+
+/// Progress-only changes closer together than this are left out.
+const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    transfers: Vec<&'a Entry>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    id: String,
+    direction: &'static str,
+    state: &'static str,
+    transferred: u64,
+    total: u64,
+    #[serde(skip)]
+    finished: bool,
+}
+
+/// Writes snapshots of the transfers in `events` to `path` until the stream
+/// ends.
+pub fn spawn(path: PathBuf, events: impl Stream<Item = OxidropEvent> + Send + Unpin + 'static) {
+    let (snapshot_send, snapshot_recv) = mpsc::channel();
+    // Opening a FIFO blocks until someone reads it, so writing gets a thread.
+    thread::spawn(move || write_snapshots(&path, snapshot_recv));
+    tokio::spawn(collect(events, snapshot_send));
+}
+
+async fn collect(
+    mut events: impl Stream<Item = OxidropEvent> + Unpin,
+    snapshot_send: mpsc::Sender<String>,
+) {
+    let mut transfers = IndexMap::<String, Entry>::new();
+    let mut last_write = None::<Instant>;
+    while let Some(event) = events.next().await {
+        let state_changed = match event {
+            OxidropEvent::TransferStateChanged {
+                id,
+                direction,
+                state,
+            } => {
+                let entry = transfers.entry(id.clone()).or_insert_with(|| Entry {
+                    id,
+                    direction: direction_name(direction),
+                    state: "",
+                    transferred: 0,
+                    total: 0,
+                    finished: false,
+                });
+                entry.state = state_name(state);
+                entry.finished = state.is_final();
+                true
+            }
+            OxidropEvent::TransferProgress { id, progress, .. } => {
+                if let Some(entry) = transfers.get_mut(&id) {
+                    entry.transferred = progress.transferred;
+                    entry.total = progress.total;
+                }
+                false
+            }
+            _ => continue,
+        };
+        if !state_changed && last_write.is_some_and(|t| t.elapsed() < MIN_INTERVAL) {
+            continue;
+        }
+
+        let snapshot = Snapshot {
+            transfers: transfers.values().collect(),
+        };
+        let json = serde_json::to_string(&snapshot).expect("snapshot serializes");
+        if snapshot_send.send(json).is_err() {
+            return;
+        }
+        last_write = Some(Instant::now());
+        transfers.retain(|_, entry| !entry.finished);
+    }
+}
+
+fn write_snapshots(path: &Path, snapshot_recv: mpsc::Receiver<String>) {
+    #[cfg(unix)]
+    let is_fifo = {
+        use std::os::unix::fs::FileTypeExt;
+        fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+    };
+    #[cfg(not(unix))]
+    let is_fifo = false;
+
+    if is_fifo {
+        let mut fifo = match fs::OpenOptions::new().write(true).open(path) {
+            Ok(f) => f,
+            Err(e) => return log::warn!("Failed to open {}: {e}", path.display()),
+        };
+        for json in snapshot_recv {
+            if let Err(e) = writeln!(fifo, "{json}") {
+                return log::warn!("Failed to write to {}: {e}", path.display());
+            }
+        }
+    } else {
+        let tmp = temp_path(path);
+        for json in snapshot_recv {
+            if let Err(e) = fs::write(&tmp, json).and_then(|()| fs::rename(&tmp, path)) {
+                log::warn!("Failed to write {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Where a snapshot is written before it replaces the one at `path`. The
+/// suffix is appended rather than swapped for the extension, which would
+/// leave `path` unchanged if it already ended in `.tmp`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+fn direction_name(direction: TransferDirection) -> &'static str {
+    match direction {
+        TransferDirection::Inbound => "inbound",
+        TransferDirection::Outbound => "outbound",
+    }
+}
+
+fn state_name(state: TransferState) -> &'static str {
+    match state {
+        TransferState::Initiated => "initiated",
+        TransferState::WaitingForConsent => "waiting_for_consent",
+        TransferState::Accepted => "accepted",
+        TransferState::Transferring => "transferring",
+        TransferState::Finalizing => "finalizing",
+        TransferState::Done => "done",
+        TransferState::Rejected => "rejected",
+        TransferState::Cancelled => "cancelled",
+        TransferState::Failed => "failed",
+        TransferState::TimedOut => "timed_out",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_path_differs_from_the_target() {
+        assert_eq!(
+            temp_path(Path::new("/run/oxidrop.json")),
+            Path::new("/run/oxidrop.json.tmp")
+        );
+        assert_eq!(
+            temp_path(Path::new("/run/progress.tmp")),
+            Path::new("/run/progress.tmp.tmp")
+        );
+    }
+}
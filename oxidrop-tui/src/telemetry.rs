@@ -0,0 +1,117 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::Context;
+use opentelemetry::{
+    KeyValue, global,
+    trace::{Span, Status, Tracer},
+};
+use opentelemetry_otlp::{MetricExporter, SpanExporter};
+use opentelemetry_sdk::{Resource, metrics::SdkMeterProvider, trace::SdkTracerProvider};
+use oxidrop::{Oxidrop, OxidropEvent, TransferDirection, TransferState};
+use tokio_stream::StreamExt;
+
+/// Exports traces and metrics over OTLP until dropped. The collector is
+/// configured through the standard `OTEL_EXPORTER_OTLP_*` variables.
+pub struct Telemetry {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        let _ = self.tracer_provider.shutdown();
+        let _ = self.meter_provider.shutdown();
+    }
+}
+
+pub fn init(oxidrop: &Oxidrop) -> anyhow::Result<Telemetry> {
+    let resource = Resource::builder().with_service_name("oxidrop").build();
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_batch_exporter(
+            SpanExporter::builder()
+                .with_http()
+                .build()
+                .context("Failed to create the OTLP span exporter")?,
+        )
+        .with_resource(resource.clone())
+        .build();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(
+            MetricExporter::builder()
+                .with_http()
+                .build()
+                .context("Failed to create the OTLP metric exporter")?,
+        )
+        .with_resource(resource)
+        .build();
+    global::set_tracer_provider(tracer_provider.clone());
+    global::set_meter_provider(meter_provider.clone());
+
+    tokio::spawn(record_transfers(oxidrop.events()));
+
+    Ok(Telemetry {
+        tracer_provider,
+        meter_provider,
+    })
+}
+
+/// Starts a span covering a discovery session, which ends when dropped.
+pub fn discovery_session() -> impl Span {
+    global::tracer("oxidrop").start("discovery")
+}
+
+/// Turns each transfer into a span from its first state to its final one,
+/// and counts finished transfers by outcome.
+async fn record_transfers(events: impl tokio_stream::Stream<Item = OxidropEvent>) {
+    let tracer = global::tracer("oxidrop");
+    let meter = global::meter("oxidrop");
+    let finished = meter
+        .u64_counter("oxidrop.transfers")
+        .with_description("Transfers that reached a final state")
+        .build();
+    let duration = meter
+        .f64_histogram("oxidrop.transfer.duration")
+        .with_unit("s")
+        .build();
+
+    let mut active = HashMap::new();
+    tokio::pin!(events);
+    while let Some(event) = events.next().await {
+        let OxidropEvent::TransferStateChanged {
+            id,
+            direction,
+            state,
+        } = event
+        else {
+            continue;
+        };
+
+        let direction = match direction {
+            TransferDirection::Inbound => "inbound",
+            TransferDirection::Outbound => "outbound",
+        };
+        let (span, started) = active.entry(id.clone()).or_insert_with(|| {
+            let mut span = tracer.start("transfer");
+            span.set_attribute(KeyValue::new("oxidrop.transfer.id", id.clone()));
+            span.set_attribute(KeyValue::new("oxidrop.transfer.direction", direction));
+            (span, Instant::now())
+        });
+        span.add_event(format!("{state:?}"), vec![]);
+
+        if state.is_final() {
+            let outcome = format!("{state:?}").to_lowercase();
+            if state != TransferState::Done {
+                span.set_status(Status::error(outcome.clone()));
+            }
+            let labels = [
+                KeyValue::new("direction", direction),
+                KeyValue::new("outcome", outcome),
+            ];
+            finished.add(1, &labels);
+            duration.record(started.elapsed().as_secs_f64(), &labels);
+            if let Some((mut span, _)) = active.remove(&id) {
+                span.end();
+            }
+        }
+    }
+}